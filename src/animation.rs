@@ -0,0 +1,110 @@
+use std::path::Path;
+use std::time::Duration;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame};
+
+use crate::grid::extend_box;
+use crate::{ExportArea, ExportFormat, ExportOptions, Layers, UndoHistory};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FramePacing {
+    // The same delay after every action
+    #[default]
+    PerAction,
+    // Delays as long as the time between the actions, with long pauses cut
+    // down to the frame delay
+    RealTime,
+}
+
+impl FramePacing {
+    pub const ALL: [FramePacing; 2] = [FramePacing::PerAction, FramePacing::RealTime];
+}
+
+impl std::fmt::Display for FramePacing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramePacing::PerAction => write!(f, "One frame per action"),
+            FramePacing::RealTime => write!(f, "Real time"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationOptions {
+    pub pacing: FramePacing,
+    // Delay after each action, or the longest pause in real time
+    pub frame_ms: u32,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> Self {
+        Self { pacing: FramePacing::PerAction, frame_ms: 100 }
+    }
+}
+
+// Shortest delay most GIF viewers respect
+const MIN_FRAME_MS: u32 = 20;
+// The finished drawing stays on screen a bit before the animation loops
+const LAST_FRAME_MS: u32 = 2000;
+
+impl AnimationOptions {
+    // Replays the undo history from the state before its first action up to
+    // `layers`, and writes each step as a frame of a looping GIF
+    pub fn save(&self, layers: &Layers, history: &UndoHistory, options: &ExportOptions, path: &Path) -> Result<(), String> {
+        let entries = history.entries();
+
+        // Every frame covers the same cells: everything drawn at some point
+        let mut bounding_box = layers.bounding_box();
+        for entry in entries {
+            for (point, _) in entry.action.painted_cells() {
+                bounding_box = Some(match bounding_box {
+                    Some(bounding_box) => extend_box(bounding_box, point),
+                    None => (point, point),
+                });
+            }
+        }
+        let mut options = *options;
+        if let (ExportArea::Drawing, Some((min, max))) = (options.area, bounding_box) {
+            options.area = ExportArea::Region(min, max);
+        }
+
+        // The frame after each action lasts until the next one
+        let mut delays = vec![self.frame_ms];
+        for (i, entry) in entries.iter().enumerate() {
+            let delay = match (self.pacing, entries.get(i + 1)) {
+                (_, None) => LAST_FRAME_MS,
+                (FramePacing::PerAction, Some(_)) => self.frame_ms,
+                (FramePacing::RealTime, Some(next)) => {
+                    let pause = next.time.duration_since(entry.time);
+                    pause.min(Duration::from_millis(self.frame_ms as u64)).as_millis() as u32
+                },
+            };
+            delays.push(delay.max(MIN_FRAME_MS));
+        }
+
+        // Undoing everything on a copy gives the first frame, and redoing the
+        // actions one by one the others, each written as soon as it's drawn
+        let mut layers = layers.clone();
+        let mut history = history.clone();
+        history.jump_to(0, &mut layers);
+
+        options.validate(ExportFormat::Gif)?;
+        let error = |error: image::ImageError| format!("Failed to save {}: {error}", path.display());
+        // Checked before creating the file, so a failed export doesn't leave
+        // an empty one behind
+        options.image_size(&layers).map_err(|error| format!("Failed to save {}: {error}", path.display()))?;
+        let file = std::fs::File::create(path)
+            .map_err(|error| format!("Failed to save {}: {error}", path.display()))?;
+        let mut encoder = GifEncoder::new(file);
+        encoder.set_repeat(Repeat::Infinite).map_err(error)?;
+        for (i, delay) in delays.into_iter().enumerate() {
+            if i > 0 {
+                history.redo(&mut layers);
+            }
+            let image = options.render(&layers).map_err(|error| format!("Failed to save {}: {error}", path.display()))?;
+            let frame = Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(delay, 1));
+            encoder.encode_frame(frame).map_err(error)?;
+        }
+        Ok(())
+    }
+}
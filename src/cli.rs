@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+
+use pixel_editor::{Atom, Color, ExportFormat, ExportOptions, Grid, GridIndex, HistoryLimits, Layers, parse_size, read_atoms, text};
+
+pub const EDITOR_USAGE: &str = "\
+Usage: pixel-editor [options]
+
+Opens the editor.
+
+  --history-entries N  Undo steps kept per document, 1000 by default
+  --history-cells N    Cells the undo history of a document keeps in memory, 1000000 by default";
+
+pub const USAGE: &str = "\
+Usage: pixel-editor render (--text PHRASE | --drawing FILE) --output FILE [options]
+
+Renders a phrase or a text drawing to an image without opening the editor.
+The output format is picked from the file extension (png, bmp, gif, webp, svg, pdf or txt).
+
+  --text PHRASE      Stamps the first pattern matching each word, one line of patterns per line
+  --drawing FILE     Text drawing, as written by the text export
+  --output FILE      File to write
+  --data FILE        Patterns to search the words in, data.csv by default
+  --scale N          Pixels per cell, 10 by default
+  --margin N         Cells of empty space around the drawing
+  --size WxH         Size in cells of the image, centered on the drawing. Overrides --margin
+  --grid-lines       Draws lines between cells
+  --transparent      Leaves the background transparent";
+
+// Reads the options of the editor, when it's run without a subcommand
+pub fn history_limits(args: &[String]) -> Result<HistoryLimits, String> {
+    let mut limits = HistoryLimits::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--history-entries" => limits.max_entries = value()?.parse()
+                .ok()
+                .filter(|&entries| entries > 0)
+                .ok_or("--history-entries must be a positive number")?,
+            "--history-cells" => limits.max_cells = value()?.parse()
+                .map_err(|_| "--history-cells must be a number of cells")?,
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+    Ok(limits)
+}
+
+enum Source {
+    Text(String),
+    Drawing(PathBuf),
+}
+
+// Runs `pixel-editor render` with the arguments after the subcommand
+pub fn render(args: &[String]) -> Result<(), String> {
+    let mut source = None;
+    let mut output = None;
+    let mut data = PathBuf::from("data.csv");
+    let mut options = ExportOptions::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {arg}"));
+        match arg.as_str() {
+            "--text" => source = Some(Source::Text(value()?.clone())),
+            "--drawing" => source = Some(Source::Drawing(PathBuf::from(value()?))),
+            "--output" | "-o" => output = Some(PathBuf::from(value()?)),
+            "--data" => data = PathBuf::from(value()?),
+            "--scale" => options.pixel_size = value()?.parse()
+                .ok()
+                .filter(|&size| size > 0)
+                .ok_or("--scale must be a positive number")?,
+            "--margin" => options.margin = value()?.parse()
+                .ok()
+                .filter(|&margin| margin >= 0)
+                .ok_or("--margin must be a number of cells")?,
+            "--size" => options.fixed_size = Some(parse_size(value()?)
+                .ok_or("--size must be like 32x16")?),
+            "--grid-lines" => options.grid_lines = true,
+            "--transparent" => options.transparent = true,
+            _ => return Err(format!("Unknown argument {arg}")),
+        }
+    }
+
+    let output = output.ok_or("Missing --output")?;
+    let format = ExportFormat::from_path(&output)
+        .ok_or(format!("Unknown format for {}", output.display()))?;
+    let grid = match source.ok_or("Missing --text or --drawing")? {
+        Source::Text(phrase) => render_phrase(&read_atoms(&data)?, &phrase)?,
+        Source::Drawing(path) => {
+            let drawing = std::fs::read_to_string(&path)
+                .map_err(|error| format!("Cannot read {}: {error}", path.display()))?;
+            text::from_text(&drawing)
+        },
+    };
+    options.save(&Layers::from(grid), format, &output)
+}
+
+// Stamps the patterns of the words side by side like typing them in the
+// editor, each line of the phrase one pattern height below the last
+fn render_phrase(atoms: &[Atom], phrase: &str) -> Result<Grid<Color>, String> {
+    let mut grid = Grid::default();
+    for (row, line) in phrase.lines().enumerate() {
+        for (column, word) in line.split_whitespace().enumerate() {
+            let atom = atoms
+                .iter()
+                .find(|atom| atom.contains(word))
+                .ok_or(format!("No pattern matches \"{word}\""))?;
+            atom.stamp(&mut grid, column as GridIndex * Atom::SIDE, row as GridIndex * Atom::SIDE);
+        }
+    }
+    Ok(grid)
+}
+
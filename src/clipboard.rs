@@ -0,0 +1,131 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use image::{DynamicImage, ImageFormat, RgbaImage};
+
+use crate::text::{self, TextStyle};
+use crate::{Color, Grid, GridIndex, GridPoint, ToRgba};
+
+// Text put on the system clipboard for the cells inside the given corners
+// (both inclusive), readable by other applications and by `from_clipboard_text`
+pub fn to_clipboard_text(grid: &Grid<Color>, min: GridPoint, max: GridPoint) -> String {
+    text::to_text(grid, TextStyle::HalfBlocks, min, max)
+}
+
+// HTML put on the system clipboard along with the text, so applications that
+// take images get the cells inside the given corners (both inclusive) as a PNG
+pub fn to_clipboard_html(grid: &Grid<Color>, min: GridPoint, max: GridPoint) -> Result<String, String> {
+    let mut png = Vec::new();
+    to_clipboard_image(grid, min, max)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|error| error.to_string())?;
+    Ok(format!(
+        "<img src=\"data:image/png;base64,{}\" style=\"image-rendering: pixelated\">",
+        encode_base64(&png),
+    ))
+}
+
+// One pixel per cell, so pasting the image back gives the same cells
+fn to_clipboard_image(grid: &Grid<Color>, (min_x, min_y): GridPoint, (max_x, max_y): GridPoint) -> RgbaImage {
+    let width = (max_x - min_x + 1) as u32;
+    let height = (max_y - min_y + 1) as u32;
+    RgbaImage::from_fn(width, height, |x, y| {
+        grid.get(min_x + x as GridIndex, min_y + y as GridIndex).to_rgba()
+    })
+}
+
+// Reads cells from an image on the system clipboard, with their top left
+// corner at the origin. Images without dark pixels give None.
+pub fn from_clipboard_image(image: RgbaImage) -> Option<Grid<Color>> {
+    let grid = from_image(&DynamicImage::ImageRgba8(image));
+    let (min, max) = grid.bounding_box()?;
+    Some(grid.copy_region(min, max))
+}
+
+// Reads cells from the system clipboard's text, which is either a text
+// drawing or the path (or file URI) of an image, with their top left corner
+// at the origin. Other text gives None.
+pub fn from_clipboard_text(clipboard: &str) -> Option<Grid<Color>> {
+    if text::is_text_drawing(clipboard) {
+        let grid = text::from_text(clipboard);
+        return grid.bounding_box().is_some().then_some(grid);
+    }
+    // File managers copy a list of URIs, one per line
+    let line = clipboard.lines().next()?.trim();
+    let path = match line.strip_prefix("file://") {
+        Some(uri) => PathBuf::from(decode_uri(uri)?),
+        None => PathBuf::from(line),
+    };
+    from_clipboard_image(image::open(path).ok()?.to_rgba8())
+}
+
+// Black cells for the dark, mostly opaque pixels of an image
+pub fn from_image(image: &DynamicImage) -> Grid<Color> {
+    let mut grid = Grid::default();
+    for (x, y, pixel) in image.to_luma_alpha8().enumerate_pixels() {
+        let [luma, alpha] = pixel.0;
+        if luma < 128 && alpha >= 128 {
+            grid.set(x.into(), y.into(), Color::Black);
+        }
+    }
+    grid
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| bits | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            // Characters past the end of the bytes are padding
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// Undoes the percent-encoding of a URI's path
+fn decode_uri(uri: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut rest = uri.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_base64_with_padding() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        assert_eq!(encode_base64(b"foob"), "Zm9vYg==");
+        assert_eq!(encode_base64(&[0xff, 0xfe, 0xfd]), "//79");
+    }
+
+    #[test]
+    fn image_round_trips() {
+        let mut grid = Grid::default();
+        grid.set(3, 2, Color::Black);
+        grid.set(5, 4, Color::Black);
+        let pasted = from_clipboard_image(to_clipboard_image(&grid, (3, 2), (5, 4))).unwrap();
+        let mut cells: Vec<_> = pasted.iter().collect();
+        cells.sort_unstable_by_key(|&(cell, _)| cell);
+        assert_eq!(cells, vec![((0, 0), Color::Black), ((2, 2), Color::Black)]);
+    }
+}
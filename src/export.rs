@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::path::Path;
+use image::{ImageFormat, Rgba, RgbaImage};
+
+use crate::animation::AnimationOptions;
+use crate::pdf::{self, PrintOptions};
+use crate::text::{self, TextStyle};
+use crate::{Color, Grid, GridIndex, GridPoint, Layers, ToRgba};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Png,
+    Bmp,
+    Gif,
+    WebP,
+    Svg,
+    Pdf,
+    Text(TextStyle),
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 9] = [
+        ExportFormat::Png,
+        ExportFormat::Bmp,
+        ExportFormat::Gif,
+        ExportFormat::WebP,
+        ExportFormat::Svg,
+        ExportFormat::Pdf,
+        ExportFormat::Text(TextStyle::Ascii),
+        ExportFormat::Text(TextStyle::HalfBlocks),
+        ExportFormat::Text(TextStyle::Braille),
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "png",
+            ExportFormat::Bmp => "bmp",
+            ExportFormat::Gif => "gif",
+            ExportFormat::WebP => "webp",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Text(_) => "txt",
+        }
+    }
+
+    // Raster format to encode with, None for vector formats
+    pub fn image_format(&self) -> Option<ImageFormat> {
+        match self {
+            ExportFormat::Png => Some(ImageFormat::Png),
+            ExportFormat::Bmp => Some(ImageFormat::Bmp),
+            ExportFormat::Gif => Some(ImageFormat::Gif),
+            ExportFormat::WebP => Some(ImageFormat::WebP),
+            ExportFormat::Svg | ExportFormat::Pdf | ExportFormat::Text(_) => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        ExportFormat::ALL.into_iter().find(|format| format.extension() == extension)
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportFormat::Text(TextStyle::Ascii) => write!(f, "TXT (ASCII)"),
+            ExportFormat::Text(TextStyle::HalfBlocks) => write!(f, "TXT (half blocks)"),
+            ExportFormat::Text(TextStyle::Braille) => write!(f, "TXT (Braille)"),
+            _ => write!(f, "{}", self.extension().to_uppercase()),
+        }
+    }
+}
+
+// Parses colors written as "#rrggbb" or "rrggbb"
+pub fn parse_hex_color(s: &str) -> Option<Rgba<u8>> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(Rgba([channel(0)?, channel(2)?, channel(4)?, 255]))
+}
+
+// Parses sizes in cells written as "WIDTHxHEIGHT", like "32x16"
+pub fn parse_size(s: &str) -> Option<(GridIndex, GridIndex)> {
+    let (width, height) = s.trim().split_once(['x', 'X'])?;
+    let width = width.trim().parse().ok().filter(|&width| width > 0)?;
+    let height = height.trim().parse().ok().filter(|&height| height > 0)?;
+    Some((width, height))
+}
+
+pub fn to_hex_color(color: Rgba<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExportArea {
+    // Everything that was drawn, trimmed to the non-default cells
+    #[default]
+    Drawing,
+    // The cells inside the given corners (both inclusive)
+    Region(GridPoint, GridPoint),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportOptions {
+    pub area: ExportArea,
+    // Cells of empty space added around the area
+    pub margin: GridIndex,
+    // Size in cells of the exported image, centered on the area. Overrides
+    // the margin.
+    pub fixed_size: Option<(GridIndex, GridIndex)>,
+    // Side of each cell in the exported image, in pixels
+    pub pixel_size: u32,
+    pub foreground: Rgba<u8>,
+    pub background: Rgba<u8>,
+    // Leaves the background cells fully transparent
+    pub transparent: bool,
+    // Draws a line between cells, on their top and left edges
+    pub grid_lines: bool,
+    // Page layout of PDF exports
+    pub print: PrintOptions,
+    // Replays the undo history instead of exporting the drawing, for GIFs
+    pub animation: Option<AnimationOptions>,
+}
+
+const GRID_LINE_COLOR: Rgba<u8> = Rgba([200, 200, 200, 255]);
+// Largest image exported, so a typo in the size or the scale fails instead of
+// using up all the memory
+const MAX_IMAGE_PIXELS: u64 = 100_000_000;
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            area: ExportArea::Drawing,
+            margin: 0,
+            fixed_size: None,
+            pixel_size: 10,
+            foreground: Color::Black.to_rgba(),
+            background: Color::White.to_rgba(),
+            transparent: false,
+            grid_lines: false,
+            print: PrintOptions::default(),
+            animation: None,
+        }
+    }
+}
+
+impl ExportOptions {
+    // Corners (both inclusive) of the cells to export, given the bounding box
+    // of the drawing. An empty drawing exports the origin cell.
+    pub fn bounds(&self, drawing: Option<(GridPoint, GridPoint)>) -> (GridPoint, GridPoint) {
+        let ((min_x, min_y), (max_x, max_y)) = match self.area {
+            ExportArea::Drawing => drawing.unwrap_or(((0, 0), (0, 0))),
+            ExportArea::Region(min, max) => (min, max),
+        };
+        match self.fixed_size {
+            Some((width, height)) => {
+                let left = min_x + (max_x - min_x + 1 - width).div_euclid(2);
+                let top = min_y + (max_y - min_y + 1 - height).div_euclid(2);
+                ((left, top), (left + width - 1, top + height - 1))
+            },
+            None => (
+                (min_x - self.margin, min_y - self.margin),
+                (max_x + self.margin, max_y + self.margin),
+            ),
+        }
+    }
+
+    // Checks the options make sense for the format, before writing anything
+    pub fn validate(&self, format: ExportFormat) -> Result<(), String> {
+        // Images have no room for lines between cells a pixel wide
+        if self.grid_lines && self.pixel_size < 2 && format.image_format().is_some() {
+            return Err("Grid lines need at least 2 pixels per cell".to_string());
+        }
+        Ok(())
+    }
+
+    // Writes the visible layers to `path` in the given format
+    pub fn save(&self, layers: &Layers, format: ExportFormat, path: &Path) -> Result<(), String> {
+        self.validate(format)?;
+        let result = match format {
+            ExportFormat::Svg => std::fs::write(path, self.render_svg(layers))
+                .map_err(|error| error.to_string()),
+            ExportFormat::Pdf => std::fs::write(path, self.render_pdf(&layers.flatten()))
+                .map_err(|error| error.to_string()),
+            ExportFormat::Text(style) => std::fs::write(path, self.render_text(&layers.flatten(), style))
+                .map_err(|error| error.to_string()),
+            _ => self.render(layers)
+                .and_then(|image| image.save_with_format(path, format.image_format().expect("Raster formats have an image format"))
+                .map_err(|error| error.to_string())),
+        };
+        result.map_err(|error| format!("Failed to save {}: {error}", path.display()))
+    }
+
+    // Width and height in pixels of the rendered image, or an error if it
+    // would be too big
+    pub fn image_size(&self, layers: &Layers) -> Result<(u32, u32), String> {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds(layers.bounding_box());
+        let side = |cells: GridIndex| u32::try_from(cells).ok()?.checked_mul(self.pixel_size);
+        match (side(max_x - min_x + 1), side(max_y - min_y + 1)) {
+            (Some(width), Some(height)) if width as u64 * height as u64 <= MAX_IMAGE_PIXELS => Ok((width, height)),
+            _ => Err(format!("The image would have more than {MAX_IMAGE_PIXELS} pixels, try a smaller size or scale")),
+        }
+    }
+
+    // Blends each visible layer's cells over the ones below at its opacity
+    pub fn render(&self, layers: &Layers) -> Result<RgbaImage, String> {
+        let (width, height) = self.image_size(layers)?;
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds(layers.bounding_box());
+        let size = self.pixel_size;
+        let background =
+            if self.transparent {
+                Rgba([0, 0, 0, 0])
+            } else {
+                self.background
+            };
+        let mut img = RgbaImage::from_pixel(width, height, background);
+        for layer in layers.visible() {
+            for ((x, y), color) in layer.grid.iter_region((min_x, min_y), (max_x, max_y)) {
+                if color != Color::Black {
+                    continue;
+                }
+                let px = (x - min_x) as u32 * size;
+                let py = (y - min_y) as u32 * size;
+                for dx in 0..size {
+                    for dy in 0..size {
+                        let pixel = img.get_pixel_mut(px + dx, py + dy);
+                        *pixel = blend(*pixel, self.foreground, layer.opacity);
+                    }
+                }
+            }
+        }
+        if self.grid_lines {
+            for (px, py, pixel) in img.enumerate_pixels_mut() {
+                // The last row and column close the outer border
+                if px % size == 0 || py % size == 0 || px == width - 1 || py == height - 1 {
+                    *pixel = GRID_LINE_COLOR;
+                }
+            }
+        }
+        Ok(img)
+    }
+
+    // Text version of `render`. Colors, scale and grid lines don't apply.
+    pub fn render_text(&self, grid: &Grid<Color>, style: TextStyle) -> String {
+        let (min, max) = self.bounds(grid.bounding_box());
+        text::to_text(grid, style, min, max)
+    }
+
+    // Printable version of `render`, split into pages at the print options'
+    // cell size. The pixel size doesn't apply.
+    pub fn render_pdf(&self, grid: &Grid<Color>) -> Vec<u8> {
+        pdf::render_pdf(grid, self)
+    }
+
+    // Vector version of `render`, where each cell is `pixel_size` units wide
+    // and filled cells are merged into as few rectangles as possible, one
+    // group per layer
+    pub fn render_svg(&self, layers: &Layers) -> String {
+        let (min, max) = self.bounds(layers.bounding_box());
+        let size = self.pixel_size as GridIndex;
+        let width = (max.0 - min.0 + 1) * size;
+        let height = (max.1 - min.1 + 1) * size;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             viewBox=\"0 0 {width} {height}\" shape-rendering=\"crispEdges\">\n"
+        );
+        if !self.transparent {
+            svg += &format!(
+                "<rect width=\"{width}\" height=\"{height}\" fill=\"{}\"/>\n",
+                to_hex_color(self.background),
+            );
+        }
+        for layer in layers.visible() {
+            svg += &format!("<g fill=\"{}\"", to_hex_color(self.foreground));
+            if layer.opacity < 1.0 {
+                svg += &format!(" fill-opacity=\"{}\"", layer.opacity);
+            }
+            svg += ">\n";
+            for ((x, y), (rect_width, rect_height)) in rectangles(&layer.grid, min, max) {
+                svg += &format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n",
+                    (x - min.0) * size,
+                    (y - min.1) * size,
+                    rect_width * size,
+                    rect_height * size,
+                );
+            }
+            svg += "</g>\n";
+        }
+        if self.grid_lines {
+            let mut path = String::new();
+            for x in (0..=width).step_by(size as usize) {
+                path += &format!("M{x} 0V{height}");
+            }
+            for y in (0..=height).step_by(size as usize) {
+                path += &format!("M0 {y}H{width}");
+            }
+            svg += &format!(
+                "<path d=\"{path}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1\"/>\n",
+                to_hex_color(GRID_LINE_COLOR),
+            );
+        }
+        svg += "</svg>\n";
+        svg
+    }
+}
+
+// `color` laid over `under` with the given opacity
+fn blend(under: Rgba<u8>, color: Rgba<u8>, opacity: f32) -> Rgba<u8> {
+    let alpha = opacity * color[3] as f32 / 255.0;
+    let under_alpha = under[3] as f32 / 255.0 * (1.0 - alpha);
+    let out_alpha = alpha + under_alpha;
+    if out_alpha == 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |i: usize| ((color[i] as f32 * alpha + under[i] as f32 * under_alpha) / out_alpha).round() as u8;
+    Rgba([channel(0), channel(1), channel(2), (out_alpha * 255.0).round() as u8])
+}
+
+// Black cells inside the given corners as rectangles of (top left corner,
+// size), stacking the rows' runs that line up
+fn rectangles(grid: &Grid<Color>, min: GridPoint, max: GridPoint) -> Vec<(GridPoint, (GridIndex, GridIndex))> {
+    // Rectangles still growing downwards, by their columns
+    let mut open: HashMap<(GridIndex, GridIndex), (GridPoint, GridIndex)> = HashMap::new();
+    let mut done = Vec::new();
+    for run in grid.runs(min, max) {
+        if run.value != Color::Black {
+            continue;
+        }
+        let (x, y) = run.start;
+        match open.get_mut(&(x, run.length)) {
+            Some((start, height)) if start.1 + *height == y => *height += 1,
+            _ => {
+                if let Some((start, height)) = open.insert((x, run.length), ((x, y), 1)) {
+                    done.push((start, (run.length, height)));
+                }
+            },
+        }
+    }
+    done.extend(open.into_iter().map(|((_, length), (start, height))| (start, (length, height))));
+    done.sort_by_key(|&((x, y), _)| (y, x));
+    done
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_size_rejects_huge_images() {
+        let layers = Layers::default();
+        let options = ExportOptions { fixed_size: Some((4, 3)), pixel_size: 5, ..ExportOptions::default() };
+        assert_eq!(options.image_size(&layers), Ok((20, 15)));
+
+        let huge = ExportOptions { fixed_size: Some((100_000, 100_000)), pixel_size: 100, ..options };
+        assert!(huge.image_size(&layers).is_err());
+        assert!(huge.render(&layers).is_err());
+        // Past what a u32 holds
+        let overflowing = ExportOptions { fixed_size: Some((1 << 40, 1)), pixel_size: 1, ..options };
+        assert!(overflowing.image_size(&layers).is_err());
+    }
+
+    #[test]
+    fn rectangles_restart_after_a_break() {
+        let mut grid = Grid::default();
+        let mut fill = |(x, y): GridPoint, width| {
+            for dx in 0..width {
+                grid.set(x + dx, y, Color::Black);
+            }
+        };
+        // A narrower run breaks up the stack in columns 1 to 3
+        fill((1, 0), 3);
+        fill((1, 1), 3);
+        fill((1, 2), 1);
+        fill((1, 3), 3);
+        fill((1, 4), 3);
+        // An empty row breaks it up in columns 6 and 7
+        fill((6, 0), 2);
+        fill((6, 2), 2);
+        assert_eq!(rectangles(&grid, (0, 0), (7, 4)), vec![
+            ((1, 0), (3, 2)),
+            ((6, 0), (2, 1)),
+            ((1, 2), (1, 1)),
+            ((6, 2), (2, 1)),
+            ((1, 3), (3, 2)),
+        ]);
+    }
+}
@@ -0,0 +1,269 @@
+use std::path::{Path, PathBuf};
+
+use iced::widget::{button, center, checkbox, column, container, mouse_area, opaque, pick_list, row, text, text_input};
+use iced::{Element, Length};
+
+use pixel_editor::{AnimationOptions, ExportFormat, FramePacing, ExportOptions, Message, PageSize, PrintOptions, parse_hex_color, parse_size, to_hex_color};
+
+// Fields of the export dialog, kept as typed until the export is confirmed
+pub struct ExportDialog {
+    path: String,
+    format: ExportFormat,
+    pixel_size: String,
+    margin: String,
+    // Width and height in cells, or empty to fit the drawing
+    size: String,
+    foreground: String,
+    background: String,
+    transparent: bool,
+    grid_lines: bool,
+    page_size: PageSize,
+    cell_mm: String,
+    registration_marks: bool,
+    numbers: bool,
+    animate: bool,
+    pacing: FramePacing,
+    frame_ms: String,
+    // Set when the file already exists, until the user confirms
+    confirm_overwrite: bool,
+    pub error: Option<String>,
+}
+
+impl ExportDialog {
+    pub fn new(path: &Path, options: &ExportOptions) -> Self {
+        Self {
+            path: path.display().to_string(),
+            format: ExportFormat::from_path(path).unwrap_or_default(),
+            pixel_size: options.pixel_size.to_string(),
+            margin: options.margin.to_string(),
+            size: options.fixed_size.map(|(width, height)| format!("{width}x{height}")).unwrap_or_default(),
+            foreground: to_hex_color(options.foreground),
+            background: to_hex_color(options.background),
+            transparent: options.transparent,
+            grid_lines: options.grid_lines,
+            page_size: options.print.page_size,
+            cell_mm: options.print.cell_mm.to_string(),
+            registration_marks: options.print.registration_marks,
+            numbers: options.print.numbers,
+            animate: options.animation.is_some(),
+            pacing: options.animation.unwrap_or_default().pacing,
+            frame_ms: options.animation.unwrap_or_default().frame_ms.to_string(),
+            confirm_overwrite: false,
+            error: None,
+        }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(&self.path)
+    }
+
+    pub fn format(&self) -> ExportFormat {
+        self.format
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::ExportPathChanged(path) => {
+                self.path = path;
+                self.confirm_overwrite = false;
+            },
+            Message::ExportFormatSelected(format) => {
+                self.format = format;
+                let mut path = self.path();
+                path.set_extension(format.extension());
+                self.path = path.display().to_string();
+                self.confirm_overwrite = false;
+            },
+            Message::ExportScaleChanged(pixel_size) => self.pixel_size = pixel_size,
+            Message::ExportMarginChanged(margin) => self.margin = margin,
+            Message::ExportSizeChanged(size) => self.size = size,
+            Message::ExportForegroundChanged(color) => self.foreground = color,
+            Message::ExportBackgroundChanged(color) => self.background = color,
+            Message::ExportTransparentToggled(transparent) => self.transparent = transparent,
+            Message::ExportGridLinesToggled(grid_lines) => self.grid_lines = grid_lines,
+            Message::ExportPageSizeSelected(page_size) => self.page_size = page_size,
+            Message::ExportCellSizeChanged(cell_mm) => self.cell_mm = cell_mm,
+            Message::ExportRegistrationMarksToggled(marks) => self.registration_marks = marks,
+            Message::ExportNumbersToggled(numbers) => self.numbers = numbers,
+            Message::ExportAnimateToggled(animate) => self.animate = animate,
+            Message::ExportPacingSelected(pacing) => self.pacing = pacing,
+            Message::ExportFrameDelayChanged(frame_ms) => self.frame_ms = frame_ms,
+            _ => {},
+        }
+    }
+
+    // Checks whether the export can go ahead, or needs the user to confirm
+    // overwriting the file first
+    pub fn needs_confirmation(&mut self) -> bool {
+        if !self.confirm_overwrite && self.path().exists() {
+            self.confirm_overwrite = true;
+        }
+        self.confirm_overwrite
+    }
+
+    // Options typed in the dialog, on top of the ones it was opened with
+    pub fn options(&self, base: &ExportOptions) -> Result<ExportOptions, String> {
+        let pixel_size = self.pixel_size.trim().parse::<u32>()
+            .ok()
+            .filter(|&size| size > 0)
+            .ok_or("Pixels per cell must be a positive number")?;
+        let margin = self.margin.trim().parse()
+            .ok()
+            .filter(|&margin| margin >= 0)
+            .ok_or("Margin must be a number of cells")?;
+        let fixed_size =
+            if self.size.trim().is_empty() {
+                None
+            } else {
+                Some(parse_size(&self.size).ok_or("Size must be empty or like 32x16")?)
+            };
+        let foreground = parse_hex_color(&self.foreground)
+            .ok_or("Foreground must be a color like #000000")?;
+        let background = parse_hex_color(&self.background)
+            .ok_or("Background must be a color like #ffffff")?;
+        let cell_mm = self.cell_mm.trim().parse::<f32>()
+            .ok()
+            .filter(|&size| size >= 1.0)
+            .ok_or("Cell size must be at least 1 mm")?;
+        let animation =
+            if self.animate {
+                let frame_ms = self.frame_ms.trim().parse()
+                    .ok()
+                    .filter(|&ms| ms > 0)
+                    .ok_or("Frame delay must be a positive number of milliseconds")?;
+                Some(AnimationOptions { pacing: self.pacing, frame_ms })
+            } else {
+                None
+            };
+        Ok(ExportOptions {
+            pixel_size,
+            margin,
+            fixed_size,
+            foreground,
+            background,
+            transparent: self.transparent,
+            grid_lines: self.grid_lines,
+            print: PrintOptions {
+                page_size: self.page_size,
+                cell_mm,
+                registration_marks: self.registration_marks,
+                numbers: self.numbers,
+            },
+            animation,
+            ..*base
+        })
+    }
+
+    pub fn view(&self) -> Element<'_, Message> {
+        let actions: Element<'_, Message> =
+            if self.confirm_overwrite {
+                column![
+                    text(format!("{} already exists.", self.path)),
+                    row![
+                        button("Cancel").style(button::secondary).on_press(Message::CloseExportDialog),
+                        button("Overwrite").style(button::danger).on_press(Message::ConfirmOverwrite),
+                    ].spacing(10),
+                ].spacing(10).into()
+            } else {
+                row![
+                    button("Cancel").style(button::secondary).on_press(Message::CloseExportDialog),
+                    button("Export").on_press(Message::ExportImage),
+                ].spacing(10).into()
+            };
+
+        // Pages replace the pixel scale for PDFs
+        let scale: Element<'_, Message> =
+            if self.format == ExportFormat::Pdf {
+                column![
+                    field("Page size", pick_list(&PageSize::ALL[..], Some(self.page_size), Message::ExportPageSizeSelected)),
+                    field("Cell size (mm)", text_input("5", &self.cell_mm)
+                        .on_input(Message::ExportCellSizeChanged)),
+                    checkbox("Registration marks", self.registration_marks)
+                        .on_toggle(Message::ExportRegistrationMarksToggled),
+                    checkbox("Row and column numbers", self.numbers)
+                        .on_toggle(Message::ExportNumbersToggled),
+                ].spacing(10).into()
+            } else {
+                field("Pixels per cell", text_input("10", &self.pixel_size)
+                    .on_input(Message::ExportScaleChanged))
+            };
+
+        // Only GIFs can be animated
+        let animation: Element<'_, Message> =
+            if self.format != ExportFormat::Gif {
+                column![].into()
+            } else if self.animate {
+                column![
+                    checkbox("Animate the undo history", true)
+                        .on_toggle(Message::ExportAnimateToggled),
+                    field("Pacing", pick_list(&FramePacing::ALL[..], Some(self.pacing), Message::ExportPacingSelected)),
+                    field(
+                        if self.pacing == FramePacing::RealTime { "Longest pause (ms)" } else { "Frame delay (ms)" },
+                        text_input("100", &self.frame_ms).on_input(Message::ExportFrameDelayChanged),
+                    ),
+                ].spacing(10).into()
+            } else {
+                checkbox("Animate the undo history", false)
+                    .on_toggle(Message::ExportAnimateToggled)
+                    .into()
+            };
+
+        let form = column![
+            text("Export").size(24),
+            field("File", text_input("export.png", &self.path)
+                .on_input(Message::ExportPathChanged)
+                .on_submit(Message::ExportImage)),
+            field("Format", pick_list(&ExportFormat::ALL[..], Some(self.format), Message::ExportFormatSelected)),
+            scale,
+            field("Margin (cells)", text_input("0", &self.margin)
+                .on_input(Message::ExportMarginChanged)),
+            field("Size (cells)", text_input("Fit the drawing", &self.size)
+                .on_input(Message::ExportSizeChanged)),
+            field("Foreground", text_input("#000000", &self.foreground)
+                .on_input(Message::ExportForegroundChanged)),
+            field("Background", text_input("#ffffff", &self.background)
+                .on_input(Message::ExportBackgroundChanged)),
+            checkbox("Transparent background", self.transparent)
+                .on_toggle(Message::ExportTransparentToggled),
+            checkbox("Grid lines", self.grid_lines)
+                .on_toggle(Message::ExportGridLinesToggled),
+            animation,
+            text(self.error.clone().unwrap_or_default()).color(iced::Color::from_rgb(0.8, 0.1, 0.1)),
+            actions,
+        ].spacing(10);
+
+        container(form)
+            .width(450)
+            .padding(20)
+            .style(container::rounded_box)
+            .into()
+    }
+}
+
+fn field<'a>(label: &'a str, input: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+    row![text(label).width(150), input.into()]
+        .spacing(10)
+        .align_y(iced::Alignment::Center)
+        .into()
+}
+
+// Shows `content` over `base`, blocking interaction with it. Clicking
+// outside of `content` sends `on_blur`.
+pub fn modal<'a>(
+    base: impl Into<Element<'a, Message>>,
+    content: impl Into<Element<'a, Message>>,
+    on_blur: Message,
+) -> Element<'a, Message> {
+    iced::widget::stack![
+        base.into(),
+        opaque(
+            mouse_area(center(opaque(content)).width(Length::Fill).height(Length::Fill).style(|_theme| {
+                container::Style {
+                    background: Some(iced::Color { a: 0.6, ..iced::Color::BLACK }.into()),
+                    ..container::Style::default()
+                }
+            }))
+            .on_press(on_blur)
+        )
+    ].into()
+}
@@ -0,0 +1,296 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use crate::{GridIndex, GridPoint};
+
+const CHUNK_SIDE: GridIndex = 32;
+const CHUNK_AREA: usize = (CHUNK_SIDE * CHUNK_SIDE) as usize;
+
+// Square tile of cells. Tiles with only default cells are dropped, so the
+// grid only grows with what is actually drawn.
+#[derive(Clone)]
+struct Chunk<T> {
+    cells: Vec<T>,
+    // Number of cells that aren't the default value
+    filled: usize,
+}
+
+impl<T> Chunk<T> where T: Default + Copy + PartialEq {
+    fn new() -> Self {
+        Self { cells: vec![T::default(); CHUNK_AREA], filled: 0 }
+    }
+
+    // Smallest and largest corners of the non-default cells, relative to
+    // the chunk
+    fn bounding_box(&self) -> Option<(GridPoint, GridPoint)> {
+        let mut points = self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, val)| **val != T::default())
+            .map(|(i, _)| (i as GridIndex % CHUNK_SIDE, i as GridIndex / CHUNK_SIDE));
+        let first = points.next()?;
+        Some(points.fold((first, first), extend_box))
+    }
+}
+
+// Chunk holding a cell, and the cell's index inside it
+fn chunk_of(x: GridIndex, y: GridIndex) -> (GridPoint, usize) {
+    let chunk = (x.div_euclid(CHUNK_SIDE), y.div_euclid(CHUNK_SIDE));
+    let index = y.rem_euclid(CHUNK_SIDE) * CHUNK_SIDE + x.rem_euclid(CHUNK_SIDE);
+    (chunk, index as usize)
+}
+
+pub(crate) fn extend_box(((min_x, min_y), (max_x, max_y)): (GridPoint, GridPoint), (x, y): GridPoint) -> (GridPoint, GridPoint) {
+    ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+}
+
+#[derive(Default, Clone)]
+pub struct Grid<T> {
+    chunks: HashMap<GridPoint, Chunk<T>>,
+    // Bumped on every change so views can tell when to redraw
+    revision: u64,
+    // Bounding box of the non-default cells, None while it has to be
+    // recomputed because a cell on its border was cleared
+    bounding_box: Cell<Option<Option<(GridPoint, GridPoint)>>>,
+}
+
+// Horizontal run of equal cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Run<T> {
+    pub start: GridPoint,
+    pub length: GridIndex,
+    pub value: T,
+}
+
+impl<T> Grid<T> where T: Default + Copy + PartialEq {
+    pub fn get(&self, x: GridIndex, y: GridIndex) -> T {
+        let (chunk, index) = chunk_of(x, y);
+        match self.chunks.get(&chunk) {
+            Some(chunk) => chunk.cells[index],
+            None => T::default(),
+        }
+    }
+
+    pub fn set(&mut self, x: GridIndex, y: GridIndex, val: T) {
+        let (chunk_index, index) = chunk_of(x, y);
+        let old = self.get(x, y);
+        if old == val {
+            return;
+        }
+        let is_default = val == T::default();
+        let chunk = self.chunks.entry(chunk_index).or_insert_with(Chunk::new);
+        chunk.cells[index] = val;
+        if old == T::default() {
+            chunk.filled += 1;
+        } else if is_default {
+            chunk.filled -= 1;
+            if chunk.filled == 0 {
+                self.chunks.remove(&chunk_index);
+            }
+        }
+        self.revision += 1;
+
+        match self.bounding_box.get() {
+            Some(Some(bounding_box)) if !is_default => {
+                self.bounding_box.set(Some(Some(extend_box(bounding_box, (x, y)))));
+            },
+            Some(None) if !is_default => {
+                self.bounding_box.set(Some(Some(((x, y), (x, y)))));
+            },
+            Some(Some(((min_x, min_y), (max_x, max_y))))
+                if x != min_x && x != max_x && y != min_y && y != max_y => {},
+            _ => self.bounding_box.set(None),
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    // Number of cells that aren't the default value
+    pub fn filled(&self) -> usize {
+        self.chunks.values().map(|chunk| chunk.filled).sum()
+    }
+
+    // Cells that aren't the default value
+    pub fn iter(&self) -> impl Iterator<Item = (GridPoint, T)> + '_ {
+        self.chunks.iter().flat_map(|(&(chunk_x, chunk_y), chunk)| {
+            chunk.cells
+                .iter()
+                .enumerate()
+                .filter(|(_, val)| **val != T::default())
+                .map(move |(i, &val)| {
+                    let x = chunk_x * CHUNK_SIDE + i as GridIndex % CHUNK_SIDE;
+                    let y = chunk_y * CHUNK_SIDE + i as GridIndex / CHUNK_SIDE;
+                    ((x, y), val)
+                })
+        })
+    }
+
+    // Cells that aren't the default value inside the given corners (both
+    // inclusive). Only the chunks overlapping the region are visited.
+    pub fn iter_region(&self, (min_x, min_y): GridPoint, (max_x, max_y): GridPoint) -> Vec<(GridPoint, T)> {
+        let (min_chunk, _) = chunk_of(min_x, min_y);
+        let (max_chunk, _) = chunk_of(max_x, max_y);
+        let region_chunks = (max_chunk.0 - min_chunk.0 + 1) * (max_chunk.1 - min_chunk.1 + 1);
+        // Huge regions have more chunk positions than stored chunks
+        let chunks: Vec<(GridPoint, &Chunk<T>)> =
+            if region_chunks as usize > self.chunks.len() {
+                self.chunks
+                    .iter()
+                    .filter(|&(&(x, y), _)| {
+                        min_chunk.0 <= x && x <= max_chunk.0 && min_chunk.1 <= y && y <= max_chunk.1
+                    })
+                    .map(|(&point, chunk)| (point, chunk))
+                    .collect()
+            } else {
+                (min_chunk.1..=max_chunk.1)
+                    .flat_map(|y| (min_chunk.0..=max_chunk.0).map(move |x| (x, y)))
+                    .filter_map(|point| self.chunks.get(&point).map(|chunk| (point, chunk)))
+                    .collect()
+            };
+        let mut cells = Vec::new();
+        for ((chunk_x, chunk_y), chunk) in chunks {
+            let left = chunk_x * CHUNK_SIDE;
+            let top = chunk_y * CHUNK_SIDE;
+            for y in top.max(min_y)..=(top + CHUNK_SIDE - 1).min(max_y) {
+                for x in left.max(min_x)..=(left + CHUNK_SIDE - 1).min(max_x) {
+                    let val = chunk.cells[((y - top) * CHUNK_SIDE + x - left) as usize];
+                    if val != T::default() {
+                        cells.push(((x, y), val));
+                    }
+                }
+            }
+        }
+        cells
+    }
+
+    // Horizontal runs of equal cells that aren't the default value, inside
+    // the given corners (both inclusive), sorted by row and then column
+    pub fn runs(&self, min: GridPoint, max: GridPoint) -> Vec<Run<T>> {
+        let mut cells = self.iter_region(min, max);
+        cells.sort_by_key(|&((x, y), _)| (y, x));
+        let mut runs: Vec<Run<T>> = Vec::new();
+        for ((x, y), value) in cells {
+            match runs.last_mut() {
+                Some(run) if run.start.1 == y && run.start.0 + run.length == x && run.value == value => {
+                    run.length += 1;
+                },
+                _ => runs.push(Run { start: (x, y), length: 1, value }),
+            }
+        }
+        runs
+    }
+
+    // Smallest and largest corners of the cells that aren't the default
+    // value, or None if there are none
+    pub fn bounding_box(&self) -> Option<(GridPoint, GridPoint)> {
+        if let Some(bounding_box) = self.bounding_box.get() {
+            return bounding_box;
+        }
+        let bounding_box = self.chunks
+            .iter()
+            .filter_map(|(&(chunk_x, chunk_y), chunk)| {
+                let ((min_x, min_y), (max_x, max_y)) = chunk.bounding_box()?;
+                let left = chunk_x * CHUNK_SIDE;
+                let top = chunk_y * CHUNK_SIDE;
+                Some(((left + min_x, top + min_y), (left + max_x, top + max_y)))
+            })
+            .reduce(|a, b| extend_box(extend_box(a, b.0), b.1));
+        self.bounding_box.set(Some(bounding_box));
+        bounding_box
+    }
+
+    // Copy of the cells inside the given corners (both inclusive), moved so
+    // `min` becomes the origin
+    pub fn copy_region(&self, min: GridPoint, max: GridPoint) -> Grid<T> {
+        let mut region = Grid::default();
+        for ((x, y), val) in self.iter_region(min, max) {
+            region.set(x - min.0, y - min.1, val);
+        }
+        region
+    }
+
+    // Sets the cells inside the given corners (both inclusive) back to the
+    // default value, returning the previous values
+    pub fn clear_region(&mut self, min: GridPoint, max: GridPoint) -> Vec<(GridPoint, T)> {
+        let cells = self.iter_region(min, max);
+        for &((x, y), _) in &cells {
+            self.set(x, y, T::default());
+        }
+        cells
+    }
+
+    // Writes the non-default cells of `other` with its origin at `at`,
+    // returning the previous values of the changed cells
+    pub fn paste(&mut self, other: &Grid<T>, at: GridPoint) -> Vec<(GridPoint, T)> {
+        let mut previous = Vec::new();
+        for ((x, y), val) in other.iter() {
+            let (x, y) = (x + at.0, y + at.1);
+            previous.push(((x, y), self.get(x, y)));
+            self.set(x, y, val);
+        }
+        previous
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // A grid with the given cells set to the value, for other modules' tests too
+    pub(crate) fn grid_with<T>(cells: &[GridPoint], value: T) -> Grid<T> where T: Default + Copy + PartialEq {
+        let mut grid = Grid::default();
+        for &(x, y) in cells {
+            grid.set(x, y, value);
+        }
+        grid
+    }
+
+    fn sorted(mut cells: Vec<(GridPoint, u8)>) -> Vec<GridPoint> {
+        cells.sort_unstable();
+        cells.into_iter().map(|(cell, _)| cell).collect()
+    }
+
+    #[test]
+    fn bounding_box_follows_set_cells() {
+        let mut grid = grid_with(&[(0, 0), (5, 3)], 1);
+        assert_eq!(grid.bounding_box(), Some(((0, 0), (5, 3))));
+
+        grid.set(-2, 7, 1);
+        assert_eq!(grid.bounding_box(), Some(((-2, 0), (5, 7))));
+
+        // Clearing a cell inside keeps the box, one on its border shrinks it
+        grid.set(1, 2, 1);
+        grid.set(1, 2, 0);
+        assert_eq!(grid.bounding_box(), Some(((-2, 0), (5, 7))));
+        grid.set(-2, 7, 0);
+        assert_eq!(grid.bounding_box(), Some(((0, 0), (5, 3))));
+
+        grid.set(0, 0, 0);
+        grid.set(5, 3, 0);
+        assert_eq!(grid.bounding_box(), None);
+        assert_eq!(grid.filled(), 0);
+
+        grid.set(3, 3, 1);
+        assert_eq!(grid.bounding_box(), Some(((3, 3), (3, 3))));
+    }
+
+    #[test]
+    fn iter_region_only_returns_cells_inside() {
+        // Cells on both sides of chunk borders, including negative ones
+        let grid = grid_with(&[(-1, -1), (0, 0), (31, 31), (32, 32), (100, 0), (5, 40), (-33, 5)], 1);
+        assert_eq!(sorted(grid.iter_region((-1, -1), (32, 32))), vec![(-1, -1), (0, 0), (31, 31), (32, 32)]);
+        assert_eq!(sorted(grid.iter_region((0, 0), (31, 31))), vec![(0, 0), (31, 31)]);
+        assert_eq!(sorted(grid.iter_region((200, 200), (300, 300))), vec![]);
+    }
+
+    #[test]
+    fn iter_region_covering_more_chunks_than_stored_matches_iter() {
+        let grid = grid_with(&[(-1, -1), (31, 31), (32, 32), (100, 0), (5, 40), (-33, 5)], 1);
+        let region = grid.iter_region((-1000, -1000), (1000, 1000));
+        assert_eq!(sorted(region), sorted(grid.iter().collect()));
+        // Only some of the stored chunks overlap this one
+        assert_eq!(sorted(grid.iter_region((-1000, -1000), (31, 1000))), vec![(-33, 5), (-1, -1), (5, 40), (31, 31)]);
+    }
+}
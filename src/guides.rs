@@ -0,0 +1,82 @@
+use crate::{GridIndex, GridPoint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    // Lines along a row boundary, at some y
+    Horizontal,
+    // Lines along a column boundary, at some x
+    Vertical,
+}
+
+// Lines between cells that placement snaps to. A line at `n` runs along the
+// top (or left) edge of the cells in row (or column) `n`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Guides {
+    horizontal: Vec<GridIndex>,
+    vertical: Vec<GridIndex>,
+}
+
+impl Guides {
+    pub fn lines(&self, axis: Axis) -> &[GridIndex] {
+        match axis {
+            Axis::Horizontal => &self.horizontal,
+            Axis::Vertical => &self.vertical,
+        }
+    }
+
+    fn lines_mut(&mut self, axis: Axis) -> &mut Vec<GridIndex> {
+        match axis {
+            Axis::Horizontal => &mut self.horizontal,
+            Axis::Vertical => &mut self.vertical,
+        }
+    }
+
+    // Moves the guide at `index` to the line `to`, or adds a guide there if
+    // `index` is None
+    pub fn move_guide(&mut self, axis: Axis, index: Option<usize>, to: GridIndex) {
+        let lines = self.lines_mut(axis);
+        match index.and_then(|index| lines.get_mut(index)) {
+            Some(line) => *line = to,
+            None => lines.push(to),
+        }
+    }
+
+    pub fn remove(&mut self, axis: Axis, index: usize) {
+        let lines = self.lines_mut(axis);
+        if index < lines.len() {
+            lines.remove(index);
+        }
+    }
+
+    // Guide closest to `line`, if any is at most `distance` away
+    fn nearest(&self, axis: Axis, line: GridIndex, distance: GridIndex) -> Option<GridIndex> {
+        self.lines(axis)
+            .iter()
+            .copied()
+            .filter(|guide| (guide - line).abs() <= distance)
+            .min_by_key(|guide| (guide - line).abs())
+    }
+
+    // Moves a cell so its top left corner lies on the guides at most
+    // `distance` cells away, to place things starting there
+    pub fn snap(&self, (x, y): GridPoint, distance: GridIndex) -> GridPoint {
+        (
+            self.nearest(Axis::Vertical, x, distance).unwrap_or(x),
+            self.nearest(Axis::Horizontal, y, distance).unwrap_or(y),
+        )
+    }
+
+    // Moves the dragged corner of a rectangle starting at `anchor` so the
+    // rectangle's outer edges lie on the guides at most `distance` cells away
+    pub fn snap_corner(&self, anchor: GridPoint, (x, y): GridPoint, distance: GridIndex) -> GridPoint {
+        let snap_edge = |axis, anchor: GridIndex, cell: GridIndex| {
+            // The far edge of a cell is the line after it
+            if cell >= anchor {
+                self.nearest(axis, cell + 1, distance).map_or(cell, |line| (line - 1).max(anchor))
+            } else {
+                self.nearest(axis, cell, distance).map_or(cell, |line| line.min(anchor))
+            }
+        };
+        (snap_edge(Axis::Vertical, anchor.0, x), snap_edge(Axis::Horizontal, anchor.1, y))
+    }
+}
@@ -0,0 +1,399 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::{Color, GridPoint, Layer, LayerId, LayerSettings, Layers, Run};
+
+// Colors given to a set of cells, stored as horizontal runs of equal colors
+// since painted cells are mostly next to each other
+#[derive(Debug, Clone, Default)]
+pub struct CellChanges {
+    // Sorted by row and then column
+    runs: Vec<Run<Color>>,
+    len: usize,
+}
+
+impl CellChanges {
+    // Number of cells changed
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = (GridPoint, Color)> + '_ {
+        self.runs.iter().flat_map(|run| {
+            (0..run.length).map(move |i| ((run.start.0 + i, run.start.1), run.value))
+        })
+    }
+}
+
+// Keeps the first color given to each cell, which for a stroke is the color
+// it had before
+impl From<Vec<(GridPoint, Color)>> for CellChanges {
+    fn from(mut cells: Vec<(GridPoint, Color)>) -> Self {
+        // The sort is stable, so the first color of a cell stays first
+        cells.sort_by_key(|&((x, y), _)| (y, x));
+        cells.dedup_by_key(|(point, _)| *point);
+        let mut runs: Vec<Run<Color>> = Vec::new();
+        for &((x, y), value) in &cells {
+            match runs.last_mut() {
+                Some(run) if run.start.1 == y && run.start.0 + run.length == x && run.value == value => {
+                    run.length += 1;
+                },
+                _ => runs.push(Run { start: (x, y), length: 1, value }),
+            }
+        }
+        runs.shrink_to_fit();
+        Self { runs, len: cells.len() }
+    }
+}
+
+// A change that can be applied to a document's layers. Each action records
+// what to restore, so applying it gives back the action that reverts it:
+// undoing turns an entry into its redo, and the other way around.
+#[derive(Clone)]
+pub enum Action {
+    // Colors to give to cells of a layer
+    Paint(LayerId, CellChanges),
+    // A layer to put back at an index
+    InsertLayer(usize, Box<Layer>),
+    DeleteLayer(LayerId),
+    // Index to move a layer to
+    MoveLayer(LayerId, usize),
+    LayerSettings(LayerId, LayerSettings),
+}
+
+impl Action {
+    // Applies the action, returning the one that reverts it, or None if its
+    // layer doesn't exist anymore
+    pub fn apply(self, layers: &mut Layers) -> Option<Action> {
+        match self {
+            Action::Paint(id, cells) => {
+                let grid = &mut layers.get_mut(id)?.grid;
+                let previous: Vec<_> = cells
+                    .cells()
+                    .map(|((x, y), color)| {
+                        let previous = grid.get(x, y);
+                        grid.set(x, y, color);
+                        ((x, y), previous)
+                    })
+                    .collect();
+                Some(Action::Paint(id, previous.into()))
+            },
+            Action::InsertLayer(index, layer) => {
+                let id = layer.id;
+                layers.insert(index, *layer);
+                Some(Action::DeleteLayer(id))
+            },
+            Action::DeleteLayer(id) => {
+                let (index, layer) = layers.remove(id)?;
+                Some(Action::InsertLayer(index, Box::new(layer)))
+            },
+            Action::MoveLayer(id, index) => {
+                let from = layers.move_to(id, index)?;
+                Some(Action::MoveLayer(id, from))
+            },
+            Action::LayerSettings(id, settings) => {
+                let previous = layers.set_settings(id, settings)?;
+                Some(Action::LayerSettings(id, previous))
+            },
+        }
+    }
+
+    // Cells whose color the action sets
+    pub fn painted_cells(&self) -> impl Iterator<Item = (GridPoint, Color)> + '_ {
+        match self {
+            Action::Paint(_, cells) => Some(cells),
+            _ => None,
+        }
+        .into_iter()
+        .flat_map(CellChanges::cells)
+    }
+
+    // Cells the action keeps in memory, to bound the history's size
+    fn stored_cells(&self) -> usize {
+        match self {
+            Action::Paint(_, cells) => cells.len(),
+            Action::InsertLayer(_, layer) => layer.grid.filled(),
+            Action::DeleteLayer(_) | Action::MoveLayer(..) | Action::LayerSettings(..) => 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HistoryEntry {
+    // Short description shown in the history panel
+    pub label: String,
+    // Reverts the entry when applied
+    pub action: Action,
+    // When the action was done, to replay the history at its pace
+    pub time: Instant,
+}
+
+// How much the history keeps before dropping its oldest entries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryLimits {
+    // Entries done and undone
+    pub max_entries: usize,
+    // Cells stored by all the entries together
+    pub max_cells: usize,
+}
+
+impl Default for HistoryLimits {
+    fn default() -> Self {
+        Self { max_entries: 1000, max_cells: 1_000_000 }
+    }
+}
+
+#[derive(Clone)]
+pub struct UndoHistory {
+    // Entries done, from the oldest to the most recent
+    stack: VecDeque<HistoryEntry>,
+    // Entries undone, the next one to redo last
+    redo_stack: VecDeque<HistoryEntry>,
+    // Number of entries done when the document was last saved, or None when
+    // that state was undone and then replaced by other actions, or dropped
+    saved_at: Option<usize>,
+    limits: HistoryLimits,
+    // Cells stored by the entries of both stacks
+    stored_cells: usize,
+    // Whether entries were dropped to stay within the limits
+    truncated: bool,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoHistory {
+    pub fn new() -> Self {
+        Self::with_limits(HistoryLimits::default())
+    }
+
+    pub fn with_limits(limits: HistoryLimits) -> Self {
+        Self {
+            stack: VecDeque::new(),
+            redo_stack: VecDeque::new(),
+            saved_at: Some(0),
+            limits,
+            stored_cells: 0,
+            truncated: false,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: HistoryLimits) {
+        self.limits = limits;
+        self.trim();
+    }
+
+    // Records a change along with the action that reverts it. Painting that
+    // changed nothing isn't recorded, and settings changed again on the same
+    // layer, like dragging its opacity slider, are merged into one entry.
+    pub fn push(&mut self, label: String, action: Action) {
+        if let Action::Paint(_, cells) = &action && cells.is_empty() {
+            return;
+        }
+        if let Some(top) = self.stack.back()
+            && top.label == label
+            && let (Action::LayerSettings(top_id, _), Action::LayerSettings(id, _)) = (&top.action, &action)
+            && top_id == id
+            && self.redo_stack.is_empty()
+            // Merging into the saved entry would change it without making
+            // the document dirty
+            && self.saved_at != Some(self.stack.len()) {
+            return;
+        }
+        if self.saved_at.is_some_and(|saved_at| saved_at > self.stack.len()) {
+            self.saved_at = None;
+        }
+        for entry in self.redo_stack.drain(..) {
+            self.stored_cells -= entry.action.stored_cells();
+        }
+        self.stored_cells += action.stored_cells();
+        self.stack.push_back(HistoryEntry { label, action, time: Instant::now() });
+        self.trim();
+    }
+
+    // Drops the oldest entries, and then the undone entries furthest from
+    // the current state, until the history is within its limits. The last
+    // entry done is always kept so it can be undone, however big it is.
+    fn trim(&mut self) {
+        while self.stack.len() + self.redo_stack.len() > self.limits.max_entries
+            || self.stored_cells > self.limits.max_cells {
+            let entry =
+                if self.stack.len() > 1 {
+                    self.saved_at = self.saved_at.and_then(|saved_at| saved_at.checked_sub(1));
+                    self.stack.pop_front().expect("The stack has more than one entry")
+                } else if let Some(entry) = self.redo_stack.pop_front() {
+                    if self.saved_at.is_some_and(|saved_at| saved_at > self.stack.len() + self.redo_stack.len()) {
+                        self.saved_at = None;
+                    }
+                    entry
+                } else {
+                    break;
+                };
+            self.stored_cells -= entry.action.stored_cells();
+            self.truncated = true;
+        }
+    }
+
+    // Reverts the last entry, returning false if there was nothing to undo
+    pub fn undo(&mut self, layers: &mut Layers) -> bool {
+        let Some(entry) = self.stack.pop_back() else {
+            return false;
+        };
+        self.stored_cells -= entry.action.stored_cells();
+        if let Some(action) = entry.action.apply(layers) {
+            self.stored_cells += action.stored_cells();
+            self.redo_stack.push_back(HistoryEntry { action, ..entry });
+        }
+        true
+    }
+
+    pub fn redo(&mut self, layers: &mut Layers) -> bool {
+        let Some(entry) = self.redo_stack.pop_back() else {
+            return false;
+        };
+        self.stored_cells -= entry.action.stored_cells();
+        if let Some(action) = entry.action.apply(layers) {
+            self.stored_cells += action.stored_cells();
+            self.stack.push_back(HistoryEntry { action, ..entry });
+        }
+        true
+    }
+
+    // Undoes or redoes entries until `position` of them are done
+    pub fn jump_to(&mut self, position: usize, layers: &mut Layers) {
+        while self.stack.len() > position && self.undo(layers) {}
+        while self.stack.len() < position && self.redo(layers) {}
+    }
+
+    // Entries done, from the oldest to the most recent
+    pub fn entries(&self) -> &VecDeque<HistoryEntry> {
+        &self.stack
+    }
+
+    // Whether older entries were dropped, so undoing everything doesn't get
+    // back to an empty document
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // Entries undone, from the next one to redo
+    pub fn undone_entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.redo_stack.iter().rev()
+    }
+
+    // For documents whose saved state isn't in the history, like recovered ones
+    pub fn mark_unsaved(&mut self) {
+        self.saved_at = None;
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_at = Some(self.stack.len());
+    }
+
+    // Whether the document changed since it was last saved. Undoing back to
+    // the saved state makes it clean again.
+    pub fn is_dirty(&self) -> bool {
+        self.saved_at != Some(self.stack.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GridIndex;
+
+    fn opacity(opacity: f32) -> Action {
+        Action::LayerSettings(1, LayerSettings { visible: true, locked: false, opacity })
+    }
+
+    // Paints one cell black in a row of its own
+    fn paint(row: GridIndex) -> Action {
+        Action::Paint(1, vec![((0, row), Color::Black)].into())
+    }
+
+    fn limits(max_entries: usize, max_cells: usize) -> HistoryLimits {
+        HistoryLimits { max_entries, max_cells }
+    }
+
+    #[test]
+    fn cell_changes_keep_the_first_color_of_each_cell() {
+        let changes = CellChanges::from(vec![
+            ((2, 0), Color::Black),
+            ((0, 1), Color::White),
+            ((0, 0), Color::White),
+            ((2, 0), Color::White),
+            ((1, 0), Color::White),
+            ((0, 0), Color::Black),
+        ]);
+        assert_eq!(changes.len(), 4);
+        assert_eq!(changes.cells().collect::<Vec<_>>(), vec![
+            ((0, 0), Color::White),
+            ((1, 0), Color::White),
+            ((2, 0), Color::Black),
+            ((0, 1), Color::White),
+        ]);
+    }
+
+    #[test]
+    fn trimming_moves_the_saved_state() {
+        let mut layers = Layers::default();
+        let mut history = UndoHistory::with_limits(limits(3, 1000));
+        history.push("Paint".to_string(), paint(0));
+        history.push("Paint".to_string(), paint(1));
+        history.mark_saved();
+        history.push("Paint".to_string(), paint(2));
+        history.push("Paint".to_string(), paint(3));
+        assert_eq!(history.entries().len(), 3);
+        assert!(history.is_truncated());
+
+        history.undo(&mut layers);
+        history.undo(&mut layers);
+        assert!(!history.is_dirty());
+    }
+
+    #[test]
+    fn trimming_the_saved_state_leaves_the_document_dirty() {
+        let mut layers = Layers::default();
+        let mut history = UndoHistory::with_limits(limits(1, 1000));
+        history.push("Paint".to_string(), paint(0));
+        history.push("Paint".to_string(), paint(1));
+        assert_eq!(history.entries().len(), 1);
+
+        history.undo(&mut layers);
+        assert!(history.entries().is_empty());
+        assert!(history.is_dirty());
+    }
+
+    #[test]
+    fn trimming_drops_undone_entries_and_keeps_the_last_one_done() {
+        let mut layers = Layers::default();
+        let mut history = UndoHistory::with_limits(limits(2, 1000));
+        history.push("Paint".to_string(), paint(0));
+        history.push("Paint".to_string(), paint(1));
+        history.undo(&mut layers);
+
+        history.set_limits(limits(1, 0));
+        assert_eq!(history.entries().len(), 1);
+        assert_eq!(history.undone_entries().count(), 0);
+    }
+
+    #[test]
+    fn merges_settings_changes_until_saved() {
+        let mut history = UndoHistory::new();
+        history.push("Opacity".to_string(), opacity(0.2));
+        history.push("Opacity".to_string(), opacity(0.3));
+        assert_eq!(history.entries().len(), 1);
+
+        history.mark_saved();
+        history.push("Opacity".to_string(), opacity(0.4));
+        assert_eq!(history.entries().len(), 2);
+        assert!(history.is_dirty());
+    }
+}
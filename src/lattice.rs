@@ -0,0 +1,90 @@
+use crate::{Atom, GridIndex, GridPoint};
+
+// How patterns and pasted cells are placed by their top left corner
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Placement {
+    // At the clicked cell, or the guides near it
+    #[default]
+    Free,
+    // At the slot of the lattice holding the clicked cell
+    Slots,
+    // At the slot of the lattice holding the clicked column, on the line of
+    // text being written
+    Baseline,
+}
+
+impl Placement {
+    pub const ALL: [Placement; 3] = [Placement::Free, Placement::Slots, Placement::Baseline];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placement::Free => "Free",
+            Placement::Slots => "Slots",
+            Placement::Baseline => "Baseline",
+        }
+    }
+}
+
+// Evenly spaced slots for patterns, each `pitch` cells wide and tall with
+// `gutter` empty cells before the next one, starting at `origin`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lattice {
+    pub pitch: GridIndex,
+    pub gutter: GridIndex,
+    pub origin: GridPoint,
+}
+
+impl Default for Lattice {
+    fn default() -> Self {
+        Self { pitch: Atom::SIDE, gutter: 1, origin: (0, 0) }
+    }
+}
+
+impl Lattice {
+    // Distance between the corners of neighbouring slots
+    pub fn step(&self) -> GridIndex {
+        (self.pitch + self.gutter).max(1)
+    }
+
+    // Top left corner of the slot holding the cell. Cells in a gutter belong
+    // to the slot before it.
+    pub fn slot(&self, (x, y): GridPoint) -> GridPoint {
+        let snap = |value: GridIndex, origin: GridIndex| {
+            origin + (value - origin).div_euclid(self.step()) * self.step()
+        };
+        (snap(x, self.origin.0), snap(y, self.origin.1))
+    }
+
+    // Top left corner of the slot after the one at `slot`, on the same line
+    pub fn next_slot(&self, (x, y): GridPoint) -> GridPoint {
+        (x + self.step(), y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_with_a_gutter_and_negative_cells() {
+        let lattice = Lattice { pitch: 5, gutter: 1, origin: (0, 0) };
+        assert_eq!(lattice.slot((0, 0)), (0, 0));
+        assert_eq!(lattice.slot((4, 4)), (0, 0));
+        // The gutter belongs to the slot before it
+        assert_eq!(lattice.slot((5, 5)), (0, 0));
+        assert_eq!(lattice.slot((6, 11)), (6, 6));
+        assert_eq!(lattice.slot((-1, -1)), (-6, -6));
+        assert_eq!(lattice.slot((-6, 0)), (-6, 0));
+        assert_eq!(lattice.slot((-7, 5)), (-12, 0));
+        assert_eq!(lattice.next_slot((-6, 0)), (0, 0));
+    }
+
+    #[test]
+    fn slots_start_at_the_origin() {
+        let lattice = Lattice { pitch: 5, gutter: 2, origin: (2, -3) };
+        assert_eq!(lattice.slot((2, -3)), (2, -3));
+        assert_eq!(lattice.slot((1, -4)), (-5, -10));
+        assert_eq!(lattice.slot((9, 3)), (9, -3));
+        assert_eq!(lattice.slot((8, 4)), (2, 4));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::grid::extend_box;
+use crate::{Color, Grid, GridPoint};
+
+// Stays the same when layers are moved, unlike their index
+pub type LayerId = u32;
+
+// What can be changed about a layer besides its cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerSettings {
+    pub visible: bool,
+    pub locked: bool,
+    pub opacity: f32,
+}
+
+#[derive(Clone)]
+pub struct Layer {
+    pub id: LayerId,
+    pub name: String,
+    pub grid: Grid<Color>,
+    pub visible: bool,
+    // Locked layers can't be drawn on
+    pub locked: bool,
+    // From 0 (invisible) to 1 (opaque)
+    pub opacity: f32,
+}
+
+impl Layer {
+    pub(crate) fn new(id: LayerId, grid: Grid<Color>) -> Self {
+        Self {
+            id,
+            name: format!("Layer {id}"),
+            grid,
+            visible: true,
+            locked: false,
+            opacity: 1.0,
+        }
+    }
+
+    pub fn settings(&self) -> LayerSettings {
+        LayerSettings { visible: self.visible, locked: self.locked, opacity: self.opacity }
+    }
+
+    // Hidden layers can't be drawn on either, so nothing changes unseen
+    pub fn editable(&self) -> bool {
+        self.visible && !self.locked
+    }
+}
+
+// Stack of layers, composited from the first (bottom) to the last (top).
+// There is always at least one layer, and one of them is active: that's the
+// one drawing goes into.
+#[derive(Clone)]
+pub struct Layers {
+    layers: Vec<Layer>,
+    active: usize,
+    next_id: LayerId,
+    // Bumped when layers are added, removed, moved, hidden or faded
+    revision: u64,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::from(Grid::default())
+    }
+}
+
+impl From<Grid<Color>> for Layers {
+    fn from(grid: Grid<Color>) -> Self {
+        Self {
+            layers: vec![Layer::new(1, grid)],
+            active: 0,
+            next_id: 2,
+            revision: 0,
+        }
+    }
+}
+
+impl Layers {
+    // Stack of the given layers, from the bottom to the top, numbered again
+    // from 1. The top one is active.
+    pub(crate) fn with_layers(mut layers: Vec<Layer>) -> Self {
+        if layers.is_empty() {
+            return Self::default();
+        }
+        for (i, layer) in layers.iter_mut().enumerate() {
+            layer.id = i as LayerId + 1;
+        }
+        Self {
+            active: layers.len() - 1,
+            next_id: layers.len() as LayerId + 1,
+            layers,
+            revision: 0,
+        }
+    }
+
+    // From the bottom to the top
+    pub fn iter(&self) -> std::slice::Iter<'_, Layer> {
+        self.layers.iter()
+    }
+
+    pub fn visible(&self) -> impl Iterator<Item = &Layer> {
+        self.layers.iter().filter(|layer| layer.visible && layer.opacity > 0.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Layer> {
+        self.layers.get(index)
+    }
+
+    pub fn get_mut(&mut self, id: LayerId) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.id == id)
+    }
+
+    pub fn active(&self) -> &Layer {
+        &self.layers[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Layer {
+        &mut self.layers[self.active]
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn select(&mut self, index: usize) {
+        if index < self.layers.len() {
+            self.active = index;
+        }
+    }
+
+    pub fn index_of(&self, id: LayerId) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.id == id)
+    }
+
+    // Adds an empty layer above the active one and makes it active
+    pub fn add(&mut self) -> LayerId {
+        let id = self.next_id;
+        self.insert(self.active + 1, Layer::new(id, Grid::default()));
+        id
+    }
+
+    // Puts a layer back at the given index and makes it active
+    pub fn insert(&mut self, index: usize, layer: Layer) {
+        self.next_id = self.next_id.max(layer.id + 1);
+        let index = index.min(self.layers.len());
+        self.layers.insert(index, layer);
+        self.active = index;
+        self.revision += 1;
+    }
+
+    // Removes a layer and returns it with its index, unless it's the last one
+    pub fn remove(&mut self, id: LayerId) -> Option<(usize, Layer)> {
+        let index = self.index_of(id)?;
+        if self.layers.len() == 1 {
+            return None;
+        }
+        let layer = self.layers.remove(index);
+        if self.active > index || self.active == self.layers.len() {
+            self.active -= 1;
+        }
+        self.revision += 1;
+        Some((index, layer))
+    }
+
+    // Moves a layer to the given index and makes it active, returning where
+    // it was
+    pub fn move_to(&mut self, id: LayerId, index: usize) -> Option<usize> {
+        let from = self.index_of(id)?;
+        let index = index.min(self.layers.len() - 1);
+        let layer = self.layers.remove(from);
+        self.layers.insert(index, layer);
+        self.active = index;
+        self.revision += 1;
+        Some(from)
+    }
+
+    // Changes a layer's settings, returning the previous ones
+    pub fn set_settings(&mut self, id: LayerId, settings: LayerSettings) -> Option<LayerSettings> {
+        let layer = self.get_mut(id)?;
+        let previous = layer.settings();
+        layer.visible = settings.visible;
+        layer.locked = settings.locked;
+        layer.opacity = settings.opacity.clamp(0.0, 1.0);
+        self.revision += 1;
+        Some(previous)
+    }
+
+    // Changes whenever the composited image might have. The first part is
+    // the stack's own revision, the second one adds up the layers' grids.
+    pub fn revision(&self) -> (u64, u64) {
+        (self.revision, self.layers.iter().map(|layer| layer.grid.revision()).sum())
+    }
+
+    // Bounding box of the visible layers' cells
+    pub fn bounding_box(&self) -> Option<(GridPoint, GridPoint)> {
+        self.visible()
+            .filter_map(|layer| layer.grid.bounding_box())
+            .reduce(|a, b| extend_box(extend_box(a, b.0), b.1))
+    }
+
+    // Single grid with the visible layers' cells, ignoring their opacity
+    pub fn flatten(&self) -> Grid<Color> {
+        let mut grid = Grid::default();
+        for layer in self.visible() {
+            for ((x, y), color) in layer.grid.iter() {
+                grid.set(x, y, color);
+            }
+        }
+        grid
+    }
+}
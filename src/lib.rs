@@ -1,26 +1,51 @@
-use std::collections::HashMap;
 use std::ops::Not;
-use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+use image::Rgba;
+
+mod animation;
+mod clipboard;
+mod export;
+mod grid;
+mod guides;
+mod history;
+mod lattice;
+mod layers;
+mod pdf;
+mod recovery;
+mod stroke;
+mod symmetry;
+pub mod text;
+
+pub use animation::{AnimationOptions, FramePacing};
+pub use clipboard::{from_clipboard_image, from_clipboard_text, from_image, to_clipboard_html, to_clipboard_text};
+pub use export::{ExportArea, ExportFormat, ExportOptions, parse_hex_color, parse_size, to_hex_color};
+pub use grid::{Grid, Run};
+pub use guides::{Axis, Guides};
+pub use history::{Action, CellChanges, HistoryEntry, HistoryLimits, UndoHistory};
+pub use lattice::{Lattice, Placement};
+pub use layers::{Layer, LayerId, LayerSettings, Layers};
+pub use pdf::{PageSize, PrintOptions};
+pub use recovery::{RECOVERY_PATH, load_recovery, save_recovery};
+pub use stroke::Stroke;
+pub use symmetry::{Symmetry, SymmetryMode};
 
 pub type GridIndex = i64;
 pub type GridPoint = (GridIndex, GridIndex);
 
+// Distance on screen from a guide within which placement snaps to it
+const GUIDE_SNAP_PIXELS: f32 = 8.0;
+
 pub trait ToRgba {
     fn to_rgba(&self) -> Rgba<u8>;
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub enum Color {
     Black,
+    #[default]
     White,
 }
 
-impl Default for Color {
-    fn default() -> Self {
-        Color::White
-    }
-}
-
 impl Not for Color {
     type Output = Color;
 
@@ -41,43 +66,115 @@ impl ToRgba for Color {
     }
 }
 
-#[derive(Default, Clone)]
-pub struct Grid<T> {
-    grid: HashMap<GridPoint, T>,
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Tool {
+    // Always paints the primary color (black), or the secondary one (white)
+    // with the right button
+    Pencil,
+    // Always paints the default color
+    Eraser,
+    // Switches the clicked cell and paints the resulting color while dragging
+    #[default]
+    Toggle,
+    // Drags a rectangle of cells to work on
+    Select,
 }
 
-impl<T> Grid<T> where T: Default + Copy + ToRgba {
-    pub fn get(&self, x: GridIndex, y: GridIndex) -> T {
-        match self.grid.get(&(x, y)) {
-            Some(&val) => val,
-            None => T::default(),
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaintButton {
+    Primary,
+    Secondary,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 4] = [Tool::Pencil, Tool::Eraser, Tool::Toggle, Tool::Select];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tool::Pencil => "Pencil",
+            Tool::Eraser => "Eraser",
+            Tool::Toggle => "Toggle",
+            Tool::Select => "Select",
+        }
+    }
+
+    // Whether the tool paints the whole brush footprint or a single cell
+    pub fn uses_brush(&self) -> bool {
+        matches!(self, Tool::Pencil | Tool::Eraser)
+    }
+
+    // Color to paint while the stroke lasts, given the color of the cell
+    // where it started
+    pub fn stroke_color(&self, button: PaintButton, start: Color) -> Color {
+        match (self, button) {
+            (Tool::Eraser, _) => Color::default(),
+            (_, PaintButton::Secondary) => Color::White,
+            (Tool::Pencil, PaintButton::Primary) => Color::Black,
+            (Tool::Toggle | Tool::Select, PaintButton::Primary) => !start,
         }
     }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BrushShape {
+    #[default]
+    Square,
+    Round,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub size: GridIndex,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self { shape: BrushShape::Square, size: 1 }
+    }
+}
+
+impl Brush {
+    pub const MAX_SIZE: GridIndex = 16;
+
+    pub fn grow(&mut self) {
+        self.size = (self.size + 1).min(Self::MAX_SIZE);
+    }
+
+    pub fn shrink(&mut self) {
+        self.size = (self.size - 1).max(1);
+    }
 
-    pub fn set(&mut self, x: GridIndex, y: GridIndex, val: T) {
-        self.grid.insert((x, y), val);
+    pub fn toggle_shape(&mut self) {
+        self.shape = match self.shape {
+            BrushShape::Square => BrushShape::Round,
+            BrushShape::Round => BrushShape::Square,
+        };
     }
 
-    pub fn render(&self, pixel_size: u32) -> RgbaImage {
-        let min_x = self.grid.keys().map(|(x, _)| *x).min().unwrap_or(0);
-        let min_y = self.grid.keys().map(|(_, y)| *y).min().unwrap_or(0);
-        let max_x = self.grid.keys().map(|(x, _)| *x).max().unwrap_or(0);
-        let max_y = self.grid.keys().map(|(_, y)| *y).max().unwrap_or(0);
-        let width = (max_x - min_x + 1) as u32;
-        let height = (max_y - min_y + 1) as u32;
-        let mut img = RgbaImage::new(pixel_size*width, pixel_size*height);
-        img.pixels_mut().for_each(|p| *p = T::default().to_rgba());
-        for ((x, y), &color) in &self.grid {
-            let rgba = color.to_rgba();
-            let px = (*x - min_x) as u32;
-            let py = (*y - min_y) as u32;
-            for dx in 0..pixel_size {
-                for dy in 0..pixel_size {
-                    img.put_pixel(px*pixel_size + dx, py*pixel_size + dy, rgba);
+    // Cells covered by the brush when centered at (x, y). Even sizes have
+    // their extra row and column to the bottom right of the center.
+    pub fn footprint(&self, x: GridIndex, y: GridIndex) -> Vec<GridPoint> {
+        let offset = (self.size - 1) / 2;
+        let center = (self.size - 1) as f32 / 2.0;
+        let radius = self.size as f32 / 2.0;
+        let mut cells = Vec::new();
+        for i in 0..self.size {
+            for j in 0..self.size {
+                let inside = match self.shape {
+                    BrushShape::Square => true,
+                    BrushShape::Round => {
+                        let dx = j as f32 - center;
+                        let dy = i as f32 - center;
+                        dx*dx + dy*dy <= radius*radius
+                    },
+                };
+                if inside {
+                    cells.push((x - offset + j, y - offset + i));
                 }
             }
         }
-        img
+        cells
     }
 }
 
@@ -91,6 +188,9 @@ pub struct Atom {
 }
 
 impl Atom {
+    // Side length of the square pattern, in cells
+    pub const SIDE: GridIndex = 5;
+
     pub fn new(words: Vec<String>, pattern: u32) -> Self {
         Self { words, pattern }
     }
@@ -99,7 +199,7 @@ impl Atom {
     // First element is the pattern represented as a u32 as described above.
     //   Base 10 with no prefix, base 2 with "0b" prefix, or base 16 with "0x" prefix.
     // The next elements are words associated with the pattern.
-    pub fn from_csv_record(record: &csv::StringRecord) -> Self {
+    pub fn from_csv_record(record: &csv::StringRecord) -> Result<Self, String> {
         let pattern_str = &record[0];
         let radix = if pattern_str.starts_with("0b") {
             2
@@ -114,13 +214,13 @@ impl Atom {
             &pattern_str[2..]
         };
         let pattern = u32::from_str_radix(number_no_prefix, radix)
-            .expect("Invalid pattern format");
+            .map_err(|_| format!("Invalid pattern \"{pattern_str}\""))?;
         let words = record
             .iter()
             .skip(1)
             .map(|s| s.trim().to_string())
             .collect();
-        Self::new(words, pattern)
+        Ok(Self::new(words, pattern))
     }
     
     pub fn contains(&self, query: &str) -> bool {
@@ -137,65 +237,435 @@ impl Atom {
             Color::White
         }
     }
+
+    // Colors of the pattern's cells with its top left corner at `(x, y)`
+    pub fn cells(&self, x: GridIndex, y: GridIndex) -> impl Iterator<Item = (GridPoint, Color)> + '_ {
+        (0..Atom::SIDE).flat_map(move |i| {
+            (0..Atom::SIDE).map(move |j| ((x + j, y + i), self.nth_bit(i * Atom::SIDE + j)))
+        })
+    }
+
+    // Writes the pattern with its top left corner at `(x, y)`, returning the
+    // previous colors of its cells
+    pub fn stamp(&self, grid: &mut Grid<Color>, x: GridIndex, y: GridIndex) -> Vec<(GridPoint, Color)> {
+        self.cells(x, y)
+            .map(|((x, y), color)| {
+                let previous = grid.get(x, y);
+                grid.set(x, y, color);
+                ((x, y), previous)
+            })
+            .collect()
+    }
+}
+
+// Reads the patterns of a CSV file without headers, in the format described
+// in `Atom::from_csv_record`
+pub fn read_atoms(path: &Path) -> Result<Vec<Atom>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)
+        .map_err(|error| format!("Cannot open {}: {error}", path.display()))?;
+    reader
+        .records()
+        .map(|result| {
+            let record = result.map_err(|error| format!("Error reading {}: {error}", path.display()))?;
+            Atom::from_csv_record(&record).map_err(|error| {
+                let line = record.position().map_or(0, |position| position.line());
+                format!("Error reading {} at line {line}: {error}", path.display())
+            })
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     SearchInputChanged(String),
     FocusSearchInput,
-    CellClicked(GridIndex, GridIndex),
+    SearchSubmitted,
+    CellClicked(GridIndex, GridIndex, PaintButton),
     CursorMovedToCell(GridIndex, GridIndex),
     MouseReleased,
+    MoveKeyboardCursor(GridIndex, GridIndex),
+    KeyboardCursorPressed,
     SelectAtom(Atom),
-    UnselectAtom,
-    ZoomIn,
-    ZoomOut,
+    SelectTool(Tool),
+    GrowBrush,
+    ShrinkBrush,
+    ToggleBrushShape,
+    // Drops the cells being pasted or the selected atom, or else the selection
+    Cancel,
+    // Zoom messages carry the point of the canvas that must stay in place
+    ZoomIn((f32, f32)),
+    ZoomOut((f32, f32)),
+    ZoomBy(f32, (f32, f32)),
+    ZoomInAtCenter,
+    ZoomOutAtCenter,
+    ResetZoom,
+    FitToDrawing,
+    MoveViewport(f32, f32),
+    PanBy(f32, f32),
+    ArrowKeyPressed(GridIndex, GridIndex),
+    GoToOrigin,
+    CanvasResized(f32, f32),
     ToggleGridVisibility,
+    ToggleMinimapVisibility,
+    ToggleRulersVisibility,
+    // Changes the cells between major grid lines by this much
+    ChangeMajorGridSpacing(GridIndex),
+    SelectPlacement(Placement),
+    SelectSymmetry(SymmetryMode),
+    // Puts the symmetry's axes at the hovered cell
+    SetSymmetryCenter,
+    // Switches the axes between running along the center cell's corner and
+    // through its middle
+    ToggleSymmetryThroughCells,
+    // Change the lattice's slot size and gutter by this many cells
+    ChangeLatticePitch(GridIndex),
+    ChangeLatticeGutter(GridIndex),
+    // Moves the lattice so a slot starts at the hovered cell
+    SetLatticeOrigin,
+    // Moves the baseline down to the next line of slots
+    NextTextLine,
+    // Moves a guide to a line between cells, or adds one there if there's
+    // no index
+    MoveGuide(Axis, Option<usize>, GridIndex),
+    RemoveGuide(Axis, usize),
+    NewDocument,
+    // Asks before closing a document or quitting with unsaved changes
+    CloseDocument(usize),
+    Quit,
+    // Answers yes to the pending question
+    Confirm,
+    DiscardRecovery,
+    Autosave,
+    SelectDocument(usize),
+    NextDocument,
+    // Copies the selection, or the whole drawing if nothing is selected, to
+    // the system clipboard too
+    CopySelection,
+    // Floats the cells on the system clipboard, as an image or text, or else
+    // the ones last copied in the editor, to be placed with a click
+    Paste,
+    AddLayer,
+    RemoveLayer,
+    SelectLayer(usize),
+    // Moves the active layer up (positive) or down the stack
+    MoveLayer(isize),
+    ToggleLayerVisibility(usize),
+    ToggleLayerLock(usize),
+    LayerOpacityChanged(usize, f32),
     Undo,
+    Redo,
+    // Undoes or redoes until this many history entries are done
+    JumpToHistory(usize),
+    OpenExportDialog,
+    CloseExportDialog,
+    ExportPathChanged(String),
+    ExportScaleChanged(String),
+    ExportMarginChanged(String),
+    ExportSizeChanged(String),
+    ExportFormatSelected(ExportFormat),
+    ExportForegroundChanged(String),
+    ExportBackgroundChanged(String),
+    ExportTransparentToggled(bool),
+    ExportGridLinesToggled(bool),
+    ExportPageSizeSelected(PageSize),
+    ExportCellSizeChanged(String),
+    ExportRegistrationMarksToggled(bool),
+    ExportNumbersToggled(bool),
+    ExportAnimateToggled(bool),
+    ExportPacingSelected(FramePacing),
+    ExportFrameDelayChanged(String),
+    // Exports with the dialog's options, asking first if the file exists
     ExportImage,
+    ConfirmOverwrite,
 }
 
-#[derive(Debug, Clone)]
-pub enum Action {
-    Paint(Vec<(GridPoint, Color)>),
-}
-
-#[derive(Default, Clone)]
-pub struct UndoHistory {
-    stack: Vec<Action>,
+// A drawing open in its own tab
+#[derive(Clone)]
+pub struct Document {
+    pub name: String,
+    pub layers: Layers,
+    pub undo_history: UndoHistory,
+    pub viewport: Viewport,
+    // Smallest and largest corners of the selected cells
+    pub selection: Option<(GridPoint, GridPoint)>,
+    pub guides: Guides,
+    // File the document was last exported to, and is exported to by default
+    pub path: PathBuf,
 }
 
-impl UndoHistory {
-    pub fn new() -> Self {
-        Self { stack: Vec::new() }
-    }
-
-    pub fn push(&mut self, action: Action) {
-        self.stack.push(action);
-    }
-
-    pub fn pop(&mut self) -> Option<Action> {
-        self.stack.pop()
+impl Document {
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            layers: Layers::default(),
+            undo_history: UndoHistory::new(),
+            viewport: Viewport::default(),
+            selection: None,
+            guides: Guides::default(),
+            path: PathBuf::from("export.png"),
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct ProgramState {
-    pub grid: Grid<Color>,
-    pub cell_size: f32,
+    // Always at least one
+    pub documents: Vec<Document>,
+    pub active_document: usize,
+    // Cells copied from a selection, with the selection's corner at the
+    // origin, ready to paste into any document
+    pub clipboard: Option<Grid<Color>>,
+    // Cells being pasted, following the mouse until a click places them
+    // with their origin at the clicked cell
+    pub floating: Option<Grid<Color>>,
     pub selected_atom: Option<Atom>,
+    pub tool: Tool,
+    pub brush: Brush,
+    // Cell used to draw from the keyboard, hidden until it is first moved
+    pub keyboard_cursor: Option<GridPoint>,
+    pub export_options: ExportOptions,
     pub grid_visible: bool,
-    pub undo_history: UndoHistory,
+    pub minimap_visible: bool,
+    pub rulers_visible: bool,
+    // Cells between the thicker grid lines, none below 2
+    pub major_grid_spacing: GridIndex,
+    // Cell under the mouse, kept after it leaves the canvas
+    pub hovered_cell: Option<GridPoint>,
+    pub placement: Placement,
+    // Applied to everything drawn with the mouse, the keyboard cursor and
+    // patterns
+    pub symmetry: Symmetry,
+    pub lattice: Lattice,
+    // Top row of the line of text written in baseline placement, set by the
+    // first pattern placed
+    pub baseline: Option<GridIndex>,
+    // Given to the history of every document
+    pub history_limits: HistoryLimits,
+    // Numbers the untitled documents
+    documents_created: usize,
 }
 
 impl Default for ProgramState {
     fn default() -> Self {
         Self {
-            grid: Grid::default(),
-            cell_size: 20.0,
+            documents: vec![Document::new("Untitled 1".to_string())],
+            active_document: 0,
+            clipboard: None,
+            floating: None,
             selected_atom: None,
+            tool: Tool::default(),
+            brush: Brush::default(),
+            keyboard_cursor: None,
+            export_options: ExportOptions::default(),
             grid_visible: true,
-            undo_history: UndoHistory::new(),
+            minimap_visible: true,
+            rulers_visible: true,
+            major_grid_spacing: Atom::SIDE,
+            hovered_cell: None,
+            placement: Placement::default(),
+            symmetry: Symmetry::default(),
+            lattice: Lattice::default(),
+            baseline: None,
+            history_limits: HistoryLimits::default(),
+            documents_created: 1,
         }
     }
-}
\ No newline at end of file
+}
+
+impl ProgramState {
+    pub fn document(&self) -> &Document {
+        &self.documents[self.active_document]
+    }
+
+    pub fn document_mut(&mut self) -> &mut Document {
+        &mut self.documents[self.active_document]
+    }
+
+    fn untitled_document(&mut self) -> Document {
+        self.documents_created += 1;
+        let mut document = Document::new(format!("Untitled {}", self.documents_created));
+        document.undo_history.set_limits(self.history_limits);
+        document
+    }
+
+    // Cells around a guide that snap to it, about the same distance on screen
+    // at any zoom
+    pub fn snap_distance(&self) -> GridIndex {
+        ((GUIDE_SNAP_PIXELS / self.document().viewport.cell_size).ceil() as GridIndex).max(1)
+    }
+
+    // Where something placed with its top left corner at `cell` goes
+    pub fn snap(&self, cell: GridPoint) -> GridPoint {
+        self.document().guides.snap(cell, self.snap_distance())
+    }
+
+    // Where a pattern or pasted cells clicked at `cell` go
+    pub fn place(&self, cell: GridPoint) -> GridPoint {
+        match self.placement {
+            Placement::Free => self.snap(cell),
+            Placement::Slots => self.lattice.slot(cell),
+            Placement::Baseline => {
+                let (x, y) = self.lattice.slot(cell);
+                (x, self.baseline.unwrap_or(y))
+            },
+        }
+    }
+
+    // Where a pattern stamped from the keyboard cursor goes. The cursor picks
+    // the line, so it isn't held to the baseline.
+    pub fn place_from_keyboard(&self, cursor: GridPoint) -> GridPoint {
+        match self.placement {
+            Placement::Free => cursor,
+            Placement::Slots | Placement::Baseline => self.lattice.slot(cursor),
+        }
+    }
+
+    // Where the keyboard cursor goes after stamping a pattern at `at`, so
+    // words can be typed one after another
+    pub fn advance(&self, at: GridPoint) -> GridPoint {
+        match self.placement {
+            Placement::Free => (at.0 + Atom::SIDE, at.1),
+            Placement::Slots | Placement::Baseline => self.lattice.next_slot(at),
+        }
+    }
+
+    // Changes the history limits of every open document, dropping what no
+    // longer fits
+    pub fn set_history_limits(&mut self, limits: HistoryLimits) {
+        self.history_limits = limits;
+        for document in &mut self.documents {
+            document.undo_history.set_limits(limits);
+        }
+    }
+
+    // Opens an empty document in a new tab and switches to it
+    pub fn new_document(&mut self) {
+        let mut document = self.untitled_document();
+        document.viewport.size = self.document().viewport.size;
+        self.documents.push(document);
+        self.active_document = self.documents.len() - 1;
+    }
+
+    pub fn select_document(&mut self, index: usize) {
+        if index < self.documents.len() {
+            // Only the visible document hears about canvas resizes
+            let size = self.document().viewport.size;
+            self.active_document = index;
+            self.document_mut().viewport.size = size;
+        }
+    }
+
+    // Closes a tab, opening an empty document if it was the last one
+    pub fn close_document(&mut self, index: usize) {
+        if index >= self.documents.len() {
+            return;
+        }
+        let size = self.document().viewport.size;
+        self.documents.remove(index);
+        if self.documents.is_empty() {
+            let document = self.untitled_document();
+            self.documents.push(document);
+        }
+        if self.active_document > index || self.active_document == self.documents.len() {
+            self.active_document -= 1;
+        }
+        self.document_mut().viewport.size = size;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    // Position of the canvas' top left corner, in pixels from the origin
+    // cell's top left corner
+    pub top_left: (f32, f32),
+    pub cell_size: f32,
+    // Size of the canvas in pixels, as last reported by it
+    pub size: (f32, f32),
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            top_left: (0.0, 0.0),
+            cell_size: Viewport::DEFAULT_CELL_SIZE,
+            size: (0.0, 0.0),
+        }
+    }
+}
+
+impl Viewport {
+    pub const DEFAULT_CELL_SIZE: f32 = 20.0;
+    pub const MIN_CELL_SIZE: f32 = 5.0;
+    pub const MAX_CELL_SIZE: f32 = 100.0;
+    pub const ZOOM_STEP: f32 = 1.1;
+
+    // Cell under a point given relative to the canvas
+    pub fn cell_at(&self, position: (f32, f32)) -> GridPoint {
+        (
+            ((position.0 + self.top_left.0) / self.cell_size).floor() as GridIndex,
+            ((position.1 + self.top_left.1) / self.cell_size).floor() as GridIndex,
+        )
+    }
+
+    pub fn center(&self) -> (f32, f32) {
+        (self.size.0 / 2.0, self.size.1 / 2.0)
+    }
+
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.top_left.0 += dx;
+        self.top_left.1 += dy;
+    }
+
+    pub fn go_to_origin(&mut self) {
+        self.top_left = (0.0, 0.0);
+    }
+
+    // Changes the cell size keeping the cell under `anchor` (relative to
+    // the canvas) in the same place
+    pub fn zoom_around(&mut self, cell_size: f32, anchor: (f32, f32)) {
+        let cell_size = cell_size.clamp(Self::MIN_CELL_SIZE, Self::MAX_CELL_SIZE);
+        let scale = cell_size / self.cell_size;
+        self.top_left.0 = (self.top_left.0 + anchor.0) * scale - anchor.0;
+        self.top_left.1 = (self.top_left.1 + anchor.1) * scale - anchor.1;
+        self.cell_size = cell_size;
+    }
+
+    // Zooms and centers the view so the given cell range fits in the canvas
+    pub fn fit(&mut self, (min_x, min_y): GridPoint, (max_x, max_y): GridPoint) {
+        // Until the canvas reports its size there's nothing to fit into
+        if self.size.0 <= 0.0 || self.size.1 <= 0.0 {
+            return;
+        }
+        let width = (max_x - min_x + 1) as f32;
+        let height = (max_y - min_y + 1) as f32;
+        self.cell_size = (self.size.0 / width)
+            .min(self.size.1 / height)
+            .floor()
+            .clamp(Self::MIN_CELL_SIZE, Self::MAX_CELL_SIZE);
+        let center_x = (min_x as f32 + width / 2.0) * self.cell_size;
+        let center_y = (min_y as f32 + height / 2.0) * self.cell_size;
+        self.top_left = (
+            center_x - self.size.0 / 2.0,
+            center_y - self.size.1 / 2.0,
+        );
+    }
+
+    // Pans the least possible so the given cell is fully visible
+    pub fn scroll_into_view(&mut self, (x, y): GridPoint) {
+        let left = x as f32 * self.cell_size;
+        let top = y as f32 * self.cell_size;
+        if left < self.top_left.0 {
+            self.top_left.0 = left;
+        } else if left + self.cell_size > self.top_left.0 + self.size.0 {
+            self.top_left.0 = left + self.cell_size - self.size.0;
+        }
+        if top < self.top_left.1 {
+            self.top_left.1 = top;
+        } else if top + self.cell_size > self.top_left.1 + self.size.1 {
+            self.top_left.1 = top + self.cell_size - self.size.1;
+        }
+    }
+}
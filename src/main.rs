@@ -1,41 +1,237 @@
 use iced::Task;
-use iced::widget::{button, canvas, column, row, text, text_input, Column};
+use iced::widget::{button, canvas, column, container, horizontal_space, row, scrollable, slider, text, text_input};
+use iced::Element;
 use iced::Length::Fill;
 use iced::keyboard;
 
 mod pixel_canvas;
 mod custom_widgets;
+mod export_dialog;
+mod cli;
 
-use pixel_editor::{Atom, Message, ProgramState, GridPoint, Color, Action};
+use pixel_editor::{Atom, Message, ProgramState, GridIndex, GridPoint, Color, Grid, Action, Placement, SymmetryMode, PaintButton, Stroke, Tool, Viewport, ExportArea, ExportFormat, Document, LayerSettings, RECOVERY_PATH, save_recovery, from_clipboard_image, from_clipboard_text, to_clipboard_html, to_clipboard_text};
 use crate::pixel_canvas::PixelCanvas;
-use std::mem;
+use crate::export_dialog::{ExportDialog, modal};
+use std::path::Path;
+use std::time::Duration;
+
+// Cells the view moves per arrow key press
+const ARROW_PAN_CELLS: f32 = 5.0;
+// How often unsaved documents are written to the recovery file
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
 
 struct App {
     search_input_string: String,
     atoms: Vec<Atom>,
-    holding_to_draw: bool,
-    // This stores the cells being changed while the mouse is held down
-    // for undo purposes
-    holding_to_draw_cells: Vec<(GridPoint, Color)>,
-    mouse_hold_value: Color, // Value to set cells to while mouse is down
+    // Cells being painted while the mouse is held down, on the active
+    // document
+    stroke: Option<Stroke>,
+    // Cell where the selection being dragged started
+    selection_anchor: Option<GridPoint>,
+    export_dialog: Option<ExportDialog>,
+    confirmation: Option<Confirmation>,
+    // Passes images to and from other applications along with text, which
+    // iced's clipboard can't. None when there's no clipboard to connect to.
+    system_clipboard: Option<arboard::Clipboard>,
+    // What went wrong with the last action, shown in the status bar until the
+    // next one
+    status: Option<String>,
     state: ProgramState,
 }
 
+// Question waiting for the user's answer before going ahead
+enum Confirmation {
+    CloseDocument(usize),
+    Quit,
+    // Documents left unsaved by the last session
+    Recover(Vec<Document>),
+}
+
 impl Default for App {
     fn default() -> Self {
         Self {
             search_input_string: String::new(),
             atoms: import_csv(),
-            holding_to_draw: false,
-            holding_to_draw_cells: Vec::new(),
-            mouse_hold_value: Color::default(),
+            stroke: None,
+            selection_anchor: None,
+            export_dialog: None,
+            confirmation: pixel_editor::load_recovery(Path::new(RECOVERY_PATH))
+                .ok()
+                .filter(|documents| !documents.is_empty())
+                .map(Confirmation::Recover),
+            system_clipboard: arboard::Clipboard::new().ok(),
+            status: None,
             state: ProgramState::default(),
         }
     }
 }
 
 impl App {
-    fn view(&self) -> Column<'_, Message> {
+    // Selected cells of the active layer, or the whole drawing if nothing is
+    // selected, moved to the origin, along with the corner opposite it
+    fn copied_cells(&self) -> Option<(Grid<Color>, GridPoint)> {
+        let document = self.state.document();
+        if let Some((min, max)) = document.selection {
+            Some((document.layers.active().grid.copy_region(min, max), (max.0 - min.0, max.1 - min.1)))
+        } else {
+            let drawing = document.layers.flatten();
+            drawing.bounding_box().map(|(min, max)| {
+                (drawing.copy_region(min, max), (max.0 - min.0, max.1 - min.1))
+            })
+        }
+    }
+
+    // Pasted cells follow the mouse until they're placed
+    fn float(&mut self, grid: Grid<Color>) {
+        self.state.selected_atom = None;
+        self.state.floating = Some(grid);
+    }
+
+    // Starts a stroke on the active layer with the color the tool paints
+    // when starting at the given cell
+    fn begin_stroke(&mut self, x: GridIndex, y: GridIndex, paint_button: PaintButton) {
+        self.end_stroke();
+        let layer = self.state.document().layers.active();
+        if layer.editable() {
+            let color = self.state.tool.stroke_color(paint_button, layer.grid.get(x, y));
+            self.stroke = Some(Stroke::new(layer.id, color));
+            self.paint_stroke_at(x, y);
+        }
+    }
+
+    // Paints the stroke color under the brush (or the single cell for tools
+    // that don't use it), and on its copies across the symmetry axes
+    fn paint_stroke_at(&mut self, x: GridIndex, y: GridIndex) {
+        let Some(stroke) = &mut self.stroke else {
+            return;
+        };
+        let cells =
+            if self.state.tool.uses_brush() {
+                self.state.brush.footprint(x, y)
+            } else {
+                vec![(x, y)]
+            };
+        let symmetry = self.state.symmetry;
+        let cells = cells.into_iter().flat_map(|cell| symmetry.images(cell));
+        stroke.paint(&mut self.state.document_mut().layers, cells);
+    }
+
+    // Records the stroke being drawn, if any, as a single history entry
+    fn end_stroke(&mut self) {
+        if let Some(stroke) = self.stroke.take() {
+            let (label, action) = stroke.finish();
+            self.state.document_mut().undo_history.push(label, action);
+        }
+    }
+
+    fn stamp_atom(&mut self, atom: &Atom, x: GridIndex, y: GridIndex) {
+        let symmetry = self.state.symmetry;
+        let document = self.state.document_mut();
+        let layer = document.layers.active_mut();
+        if layer.editable() {
+            let cells = symmetry.paint(&mut layer.grid, atom.cells(x, y));
+            let id = layer.id;
+            let label = format!("Stamp '{}'", atom.words.first().map(String::as_str).unwrap_or_default());
+            document.undo_history.push(label, Action::Paint(id, cells.into()));
+        }
+    }
+
+    // Whether the active layer can be drawn on, saying why not in the status
+    // bar otherwise
+    fn check_editable(&mut self) -> bool {
+        let layer = self.state.document().layers.active();
+        if layer.editable() {
+            return true;
+        }
+        let reason = if layer.locked { "locked" } else { "hidden" };
+        self.status = Some(format!("{} is {reason}", layer.name));
+        false
+    }
+
+    // Stamps the pattern where the keyboard cursor places it and moves the
+    // cursor past it
+    fn stamp_at_keyboard_cursor(&mut self, atom: &Atom, cursor: GridPoint) {
+        let (x, y) = self.state.place_from_keyboard(cursor);
+        self.stamp_atom(atom, x, y);
+        self.state.keyboard_cursor = Some(self.state.advance((x, y)));
+    }
+
+    // Pastes the floating cells with their origin at the given cell
+    fn place_floating(&mut self, floating: &Grid<Color>, at: GridPoint) {
+        let document = self.state.document_mut();
+        let layer = document.layers.active_mut();
+        if layer.editable() {
+            let previous = layer.grid.paste(floating, at);
+            let id = layer.id;
+            document.undo_history.push(format!("Paste {} cells", previous.len()), Action::Paint(id, previous.into()));
+            // Selecting what was pasted lets it be copied again right away
+            if let Some((min, max)) = floating.bounding_box() {
+                document.selection = Some(((at.0 + min.0, at.1 + min.1), (at.0 + max.0, at.1 + max.1)));
+            }
+        }
+    }
+
+    // Applies a change to the active document's layers and records it for
+    // undo
+    fn change_layers(&mut self, label: String, action: Action) {
+        let document = self.state.document_mut();
+        if let Some(revert) = action.apply(&mut document.layers) {
+            document.undo_history.push(label, revert);
+        }
+    }
+
+    // Changes the settings of the layer at `index` in the active document.
+    // `change` returns the verb to describe it with in the history.
+    fn change_layer_settings(&mut self, index: usize, change: impl FnOnce(&mut LayerSettings) -> &'static str) {
+        let Some(layer) = self.state.document().layers.get(index) else {
+            return;
+        };
+        let mut settings = layer.settings();
+        let label = format!("{} {}", change(&mut settings), layer.name);
+        self.change_layers(label, Action::LayerSettings(layer.id, settings));
+    }
+
+    // Writes the image with the export dialog's options, closing it unless
+    // something went wrong
+    fn export(&mut self) {
+        let Some(dialog) = &mut self.export_dialog else {
+            return;
+        };
+        let options = match dialog.options(&self.state.export_options) {
+            Ok(options) => options,
+            Err(error) => {
+                dialog.error = Some(error);
+                return;
+            },
+        };
+        // The selection, when there is one, is what gets exported
+        let mut render_options = options;
+        let document = self.state.document_mut();
+        if let Some((min, max)) = document.selection {
+            render_options.area = ExportArea::Region(min, max);
+        }
+        let result = match options.animation {
+            Some(animation) if dialog.format() == ExportFormat::Gif => {
+                animation.save(&document.layers, &document.undo_history, &render_options, &dialog.path())
+            },
+            _ => render_options.save(&document.layers, dialog.format(), &dialog.path()),
+        };
+        match result {
+            Ok(()) => {
+                // Exporting is what saves a document for now
+                document.path = dialog.path();
+                if let Some(name) = document.path.file_name() {
+                    document.name = name.to_string_lossy().into_owned();
+                }
+                document.undo_history.mark_saved();
+                self.state.export_options = options;
+                self.export_dialog = None;
+            },
+            Err(error) => dialog.error = Some(error),
+        }
+    }
+
+    fn view(&self) -> Element<'_, Message> {
         let search_results =
             if !self.search_input_string.is_empty() {
                 column(
@@ -58,19 +254,281 @@ impl App {
                 column![]
             };
 
-        column![
-            text_input("Search...", &self.search_input_string)
-                .id("search_input")
-                .on_input(Message::SearchInputChanged)
-                .width(Fill),
+        let tool_palette = row(
+            Tool::ALL.iter().map(|&tool| {
+                button(text(tool.name()))
+                    .style(if tool == self.state.tool { button::primary } else { button::secondary })
+                    .on_press(Message::SelectTool(tool))
+                    .into()
+            })
+        ).spacing(5);
+
+        let brush_indicator = text(format!(
+            "Brush: {:?} {}",
+            self.state.brush.shape,
+            self.state.brush.size,
+        ));
+
+        let content = column![
+            row![
+                text_input("Search...", &self.search_input_string)
+                    .id("search_input")
+                    .on_input(Message::SearchInputChanged)
+                    .on_submit(Message::SearchSubmitted)
+                    .width(Fill),
+                tool_palette,
+                brush_indicator,
+                self.symmetry_controls(),
+            ].spacing(10).align_y(iced::Alignment::Center),
             search_results,
-            canvas(PixelCanvas::new(&self.state))
-                .width(Fill)
-                .height(Fill)
-        ].padding(10).spacing(10)
+            self.tab_bar(),
+            row![
+                canvas(PixelCanvas::new(&self.state))
+                    .width(Fill)
+                    .height(Fill),
+                column![self.layers_panel(), self.history_panel()].spacing(20),
+            ].spacing(10),
+            self.status_bar(),
+        ].padding(10).spacing(10);
+
+        if let Some(confirmation) = &self.confirmation {
+            return modal(content, self.confirmation_view(confirmation), Message::Cancel);
+        }
+        match &self.export_dialog {
+            Some(dialog) => modal(content, dialog.view(), Message::CloseExportDialog),
+            None => content.into(),
+        }
+    }
+
+    fn confirmation_view<'a>(&self, confirmation: &'a Confirmation) -> Element<'a, Message> {
+        let (question, cancel, confirm) = match confirmation {
+            Confirmation::CloseDocument(index) => (
+                format!("{} has changes that weren't exported. Close it anyway?", self.state.documents[*index].name),
+                button("Cancel").style(button::secondary).on_press(Message::Cancel),
+                button("Close").style(button::danger).on_press(Message::Confirm),
+            ),
+            Confirmation::Quit => (
+                format!(
+                    "{} document(s) have changes that weren't exported. Quit anyway?",
+                    self.state.documents.iter().filter(|document| document.undo_history.is_dirty()).count(),
+                ),
+                button("Cancel").style(button::secondary).on_press(Message::Cancel),
+                button("Quit").style(button::danger).on_press(Message::Confirm),
+            ),
+            Confirmation::Recover(documents) => (
+                format!(
+                    "The last session ended with unsaved changes in {}. Recover them?",
+                    documents.iter().map(|document| document.name.as_str()).collect::<Vec<_>>().join(", "),
+                ),
+                button("Discard").style(button::danger).on_press(Message::DiscardRecovery),
+                button("Recover").on_press(Message::Confirm),
+            ),
+        };
+        container(
+            column![text(question), row![cancel, confirm].spacing(10)].spacing(20)
+        )
+            .width(400)
+            .padding(20)
+            .style(container::rounded_box)
+            .into()
+    }
+
+    // Keeps the documents with unsaved changes in the recovery file, or
+    // removes it when there are none
+    fn autosave(&self) {
+        // The recovery file is kept until the user decides what to do with it
+        if matches!(self.confirmation, Some(Confirmation::Recover(_))) {
+            return;
+        }
+        let path = Path::new(RECOVERY_PATH);
+        let unsaved: Vec<&Document> = self.state.documents
+            .iter()
+            .filter(|document| document.undo_history.is_dirty())
+            .collect();
+        if unsaved.is_empty() {
+            if path.exists() && let Err(error) = std::fs::remove_file(path) {
+                eprintln!("Failed to remove {}: {error}", path.display());
+            }
+        } else if let Err(error) = save_recovery(unsaved, path) {
+            eprintln!("{error}");
+        }
     }
     
+    // One tab per open document, marked with a `*` when it has changes that
+    // weren't exported yet
+    fn tab_bar(&self) -> Element<'_, Message> {
+        let tabs = self.state.documents.iter().enumerate().map(|(index, document)| {
+            let name =
+                if document.undo_history.is_dirty() {
+                    format!("{} *", document.name)
+                } else {
+                    document.name.clone()
+                };
+            let style = if index == self.state.active_document { button::primary } else { button::secondary };
+            row![
+                button(text(name)).style(style).on_press(Message::SelectDocument(index)),
+                button(text("x").size(12)).style(style).on_press(Message::CloseDocument(index)),
+            ].align_y(iced::Alignment::Center).into()
+        });
+        row(tabs)
+            .push(button("+").style(button::secondary).on_press(Message::NewDocument))
+            .spacing(5)
+            .into()
+    }
+
+    // Cell under the mouse, size of the selection and zoom, what went wrong
+    // with the last action, and the spacing of the major grid lines
+    fn status_bar(&self) -> Element<'_, Message> {
+        let hovered = match self.state.hovered_cell {
+            Some((x, y)) => format!("{x}, {y}"),
+            None => "-".to_string(),
+        };
+        let selection = match self.state.document().selection {
+            Some((min, max)) => format!("Selection: {} x {}", max.0 - min.0 + 1, max.1 - min.1 + 1),
+            None => "No selection".to_string(),
+        };
+        let major_lines =
+            if self.state.major_grid_spacing > 1 {
+                format!("Major lines every {} cells", self.state.major_grid_spacing)
+            } else {
+                "No major lines".to_string()
+            };
+        row![
+            text(hovered).width(120),
+            text(selection).width(180),
+            text(format!("{} px per cell", self.state.document().viewport.cell_size)),
+            text(self.status.clone().unwrap_or_default()).color(iced::Color::from_rgb(0.8, 0.1, 0.1)),
+            horizontal_space(),
+            self.placement_controls(),
+            text(major_lines),
+            button(text("-")).style(button::secondary).on_press(Message::ChangeMajorGridSpacing(-1)),
+            button(text("+")).style(button::secondary).on_press(Message::ChangeMajorGridSpacing(1)),
+        ].spacing(10).align_y(iced::Alignment::Center).into()
+    }
+
+    // Symmetry modes, and where the axes run when there are any
+    fn symmetry_controls(&self) -> Element<'_, Message> {
+        let symmetry = self.state.symmetry;
+        let modes = row(
+            SymmetryMode::ALL.iter().map(|&mode| {
+                button(text(mode.name()))
+                    .style(if mode == symmetry.mode { button::primary } else { button::secondary })
+                    .on_press(Message::SelectSymmetry(mode))
+                    .into()
+            })
+        ).spacing(5);
+        let mut controls = row![text("Mirror:"), modes].spacing(10).align_y(iced::Alignment::Center);
+        if symmetry.mode != SymmetryMode::Off {
+            controls = controls.push(
+                button(text(if symmetry.through_cells { "Through cells" } else { "Between cells" }))
+                    .style(button::secondary)
+                    .on_press(Message::ToggleSymmetryThroughCells)
+            );
+        }
+        controls.into()
+    }
+
+    // Placement modes, and the size of the lattice's slots and gutters when
+    // they are used
+    fn placement_controls(&self) -> Element<'_, Message> {
+        let modes = row(
+            Placement::ALL.iter().map(|&placement| {
+                button(text(placement.name()))
+                    .style(if placement == self.state.placement { button::primary } else { button::secondary })
+                    .on_press(Message::SelectPlacement(placement))
+                    .into()
+            })
+        ).spacing(5);
+        let mut controls = row![text("Placement:"), modes].spacing(10).align_y(iced::Alignment::Center);
+        if self.state.placement != Placement::Free {
+            let lattice = self.state.lattice;
+            controls = controls.push(row![
+                text(format!("Slot {}", lattice.pitch)),
+                button(text("-")).style(button::secondary).on_press(Message::ChangeLatticePitch(-1)),
+                button(text("+")).style(button::secondary).on_press(Message::ChangeLatticePitch(1)),
+                text(format!("Gutter {}", lattice.gutter)),
+                button(text("-")).style(button::secondary).on_press(Message::ChangeLatticeGutter(-1)),
+                button(text("+")).style(button::secondary).on_press(Message::ChangeLatticeGutter(1)),
+            ].spacing(5).align_y(iced::Alignment::Center));
+        }
+        controls.into()
+    }
+
+    // Layers from the top of the stack down, each with its visibility, lock
+    // and opacity, and the buttons to add, remove and move them
+    fn layers_panel(&self) -> Element<'_, Message> {
+        let layers = &self.state.document().layers;
+        let rows = layers.iter().enumerate().rev().map(|(index, layer)| {
+            column![
+                row![
+                    button(text(&layer.name).width(Fill))
+                        .style(if index == layers.active_index() { button::primary } else { button::secondary })
+                        .on_press(Message::SelectLayer(index))
+                        .width(Fill),
+                    button(text(if layer.visible { "Hide" } else { "Show" }).size(12))
+                        .style(button::secondary)
+                        .on_press(Message::ToggleLayerVisibility(index)),
+                    button(text(if layer.locked { "Unlock" } else { "Lock" }).size(12))
+                        .style(button::secondary)
+                        .on_press(Message::ToggleLayerLock(index)),
+                ].spacing(5).align_y(iced::Alignment::Center),
+                slider(0.0..=1.0, layer.opacity, move |opacity| Message::LayerOpacityChanged(index, opacity))
+                    .step(0.05),
+            ].spacing(5).into()
+        });
+        let first = layers.active_index() == 0;
+        let last = layers.active_index() + 1 == layers.len();
+        column![
+            text("Layers"),
+            column(rows).spacing(10),
+            row![
+                button("+").on_press(Message::AddLayer),
+                button("-").style(button::danger).on_press_maybe((layers.len() > 1).then_some(Message::RemoveLayer)),
+                button("Up").style(button::secondary).on_press_maybe((!last).then_some(Message::MoveLayer(1))),
+                button("Down").style(button::secondary).on_press_maybe((!first).then_some(Message::MoveLayer(-1))),
+            ].spacing(5),
+        ].spacing(10).width(220).into()
+    }
+
+    // Entries of the active document's history, from the oldest. Clicking one
+    // undoes or redoes everything after or up to it.
+    fn history_panel(&self) -> Element<'_, Message> {
+        let history = &self.state.document().undo_history;
+        let done = history.entries().len();
+        let start = if history.is_truncated() { "Oldest kept" } else { "Start" };
+        let labels = std::iter::once(start)
+            .chain(history.entries().iter().map(|entry| entry.label.as_str()))
+            .chain(history.undone_entries().map(|entry| entry.label.as_str()));
+        let entries = labels.enumerate().map(|(position, label)| {
+            let style =
+                if position == done {
+                    button::primary
+                } else if position < done {
+                    button::secondary
+                } else {
+                    button::text
+                };
+            button(text(label).size(14))
+                .style(style)
+                .width(Fill)
+                .on_press(Message::JumpToHistory(position))
+                .into()
+        });
+        column![
+            text("History"),
+            scrollable(column(entries).spacing(2)).anchor_bottom().height(Fill),
+        ].spacing(10).width(220).into()
+    }
+
     fn update(&mut self, message: Message) -> Task<Message> {
+        // Anything else that happens while drawing, like undoing or switching
+        // layers, finishes the stroke first so it's recorded where it was drawn
+        if self.stroke.is_some() && !continues_stroke(&message) {
+            self.end_stroke();
+        }
+        if !continues_stroke(&message) {
+            self.status = None;
+        }
         match message {
             Message::SearchInputChanged(s) => {
                 self.search_input_string = s;
@@ -79,130 +537,618 @@ impl App {
             Message::FocusSearchInput => {
                 text_input::focus("search_input")
             },
-            Message::CellClicked(x, y) => {
-                if let Some(atom) = &self.state.selected_atom {
+            Message::SearchSubmitted => {
+                // Picks the first result, and stamps it right away if the
+                // keyboard cursor is in use so words can be typed one after
+                // another without leaving the search bar
+                let first_match = self.atoms
+                    .iter()
+                    .find(|atom| atom.contains(&self.search_input_string))
+                    .cloned();
+                if let Some(atom) = first_match {
+                    if let Some(cursor) = self.state.keyboard_cursor {
+                        // The word stays in the search bar if it can't be stamped
+                        if self.check_editable() {
+                            self.search_input_string.clear();
+                            self.stamp_at_keyboard_cursor(&atom, cursor);
+                        }
+                    } else {
+                        self.search_input_string.clear();
+                        self.state.floating = None;
+                        self.state.selected_atom = Some(atom);
+                    }
+                }
+                Task::none()
+            },
+            Message::MoveKeyboardCursor(dx, dy) => {
+                let (x, y) = self.state.keyboard_cursor.unwrap_or((0, 0));
+                self.state.keyboard_cursor = Some((x + dx, y + dy));
+                self.state.document_mut().viewport.scroll_into_view((x + dx, y + dy));
+                Task::none()
+            },
+            Message::KeyboardCursorPressed => {
+                let Some((x, y)) = self.state.keyboard_cursor else {
+                    return Task::none();
+                };
+                // Not in the middle of a mouse drag
+                if self.stroke.is_some() || self.selection_anchor.is_some() {
+                    return Task::none();
+                }
+                // What's being placed is kept for another layer
+                if !self.check_editable() {
+                    return Task::none();
+                }
+                if let Some(floating) = self.state.floating.take() {
+                    self.place_floating(&floating, (x, y));
+                } else if let Some(atom) = self.state.selected_atom.take() {
+                    self.stamp_at_keyboard_cursor(&atom, (x, y));
+                } else {
+                    let symmetry = self.state.symmetry;
+                    let document = self.state.document_mut();
+                    let layer = document.layers.active_mut();
+                    let old_color = layer.grid.get(x, y);
+                    let previous = symmetry.paint(&mut layer.grid, [((x, y), !old_color)]);
+                    let id = layer.id;
+                    document.undo_history.push("Toggle cell".to_string(), Action::Paint(id, previous.into()));
+                }
+                Task::none()
+            },
+            Message::CellClicked(x, y, paint_button) => {
+                // Using the mouse leaves keyboard drawing mode
+                self.state.keyboard_cursor = None;
+                // Things placed by their top left corner snap to the guides
+                // or the lattice
+                let placed = self.state.place((x, y));
+                let placing = self.state.floating.is_some() || self.state.selected_atom.is_some();
+                // What's being placed is kept for another layer
+                if placing && !self.check_editable() {
+                    return Task::none();
+                }
+                if let Some(floating) = self.state.floating.take() {
+                    self.place_floating(&floating, placed);
+                } else if let Some(atom) = self.state.selected_atom.take() {
                     // TODO: Left click to paste only back pixels, right click to paste both
                     // and erase pixels
-                    let mut cells: Vec<(GridPoint, Color)> = Vec::new();
-                    for i in 0..5 {
-                        let y = y + i;
-                        for j in 0..5 {
-                            let x = x + j;
-                            cells.push(((x, y), self.state.grid.get(x, y)));
-                            self.state.grid.set(x, y, atom.nth_bit(i * 5 + j));
-                        }
+                    self.stamp_atom(&atom, placed.0, placed.1);
+                    if self.state.placement == Placement::Baseline {
+                        self.state.baseline = Some(placed.1);
                     }
-                    self.state.selected_atom = None;
-                    self.state.undo_history.push(Action::Paint(cells));
+                } else if self.state.tool == Tool::Select {
+                    let snapped = self.state.snap((x, y));
+                    self.selection_anchor = Some(snapped);
+                    self.state.document_mut().selection = Some((snapped, snapped));
                 } else {
-                    self.holding_to_draw = true;
-                    self.holding_to_draw_cells.push(
-                        ((x, y), self.state.grid.get(x, y))
-                    );
-                    let new_color = !self.state.grid.get(x, y);
-                    self.state.grid.set(x, y, new_color);
-                    self.mouse_hold_value = new_color;
+                    self.begin_stroke(x, y, paint_button);
                 }
                 Task::none()
             },
             Message::SelectAtom(atom) => {
+                self.state.floating = None;
                 self.state.selected_atom = Some(atom);
                 Task::none()
             },
-            Message::UnselectAtom => {
-                self.state.selected_atom = None;
+            Message::SelectTool(tool) => {
+                self.state.tool = tool;
+                Task::none()
+            },
+            Message::GrowBrush => {
+                self.state.brush.grow();
+                Task::none()
+            },
+            Message::ShrinkBrush => {
+                self.state.brush.shrink();
+                Task::none()
+            },
+            Message::ToggleBrushShape => {
+                self.state.brush.toggle_shape();
+                Task::none()
+            },
+            Message::Cancel => {
+                if let Some(confirmation) = &self.confirmation {
+                    // Recovery needs an answer, so the recovered work isn't
+                    // lost by accident
+                    if !matches!(confirmation, Confirmation::Recover(_)) {
+                        self.confirmation = None;
+                    }
+                } else if self.export_dialog.is_some() {
+                    self.export_dialog = None;
+                } else if self.state.floating.is_some() {
+                    self.state.floating = None;
+                } else if self.state.selected_atom.is_some() {
+                    self.state.selected_atom = None;
+                } else {
+                    self.state.document_mut().selection = None;
+                }
                 Task::none()
             },
             Message::CursorMovedToCell(x, y) => {
-                if self.holding_to_draw &&
-                   self.state.grid.get(x, y) != self.mouse_hold_value {
-                    self.holding_to_draw_cells.push(((x, y), self.state.grid.get(x, y)));
-                    self.state.grid.set(x, y, self.mouse_hold_value);
+                self.state.hovered_cell = Some((x, y));
+                if let Some(anchor) = self.selection_anchor {
+                    let distance = self.state.snap_distance();
+                    let document = self.state.document_mut();
+                    let (x, y) = document.guides.snap_corner(anchor, (x, y), distance);
+                    let (anchor_x, anchor_y) = anchor;
+                    document.selection = Some((
+                        (anchor_x.min(x), anchor_y.min(y)),
+                        (anchor_x.max(x), anchor_y.max(y)),
+                    ));
                 }
+                self.paint_stroke_at(x, y);
                 Task::none()
             },
             Message::MouseReleased => {
-                if self.holding_to_draw {
-                    self.holding_to_draw = false;
-                    self.state.undo_history.push(
-                        Action::Paint(mem::replace(&mut self.holding_to_draw_cells, Vec::new()))
-                    );
+                self.selection_anchor = None;
+                self.end_stroke();
+                Task::none()
+            },
+            Message::ZoomIn(anchor) => {
+                let viewport = &mut self.state.document_mut().viewport;
+                let cell_size =
+                    if viewport.cell_size <= 10.0 {
+                        viewport.cell_size + 1.0
+                    } else {
+                        viewport.cell_size * Viewport::ZOOM_STEP
+                    };
+                viewport.zoom_around(cell_size.floor(), anchor);
+                Task::none()
+            },
+            Message::ZoomOut(anchor) => {
+                let viewport = &mut self.state.document_mut().viewport;
+                let cell_size = viewport.cell_size / Viewport::ZOOM_STEP;
+                viewport.zoom_around(cell_size.floor(), anchor);
+                Task::none()
+            },
+            Message::ZoomBy(factor, anchor) => {
+                let viewport = &mut self.state.document_mut().viewport;
+                viewport.zoom_around(viewport.cell_size * factor, anchor);
+                Task::none()
+            },
+            Message::ZoomInAtCenter => {
+                self.update(Message::ZoomIn(self.state.document().viewport.center()))
+            },
+            Message::ZoomOutAtCenter => {
+                self.update(Message::ZoomOut(self.state.document().viewport.center()))
+            },
+            Message::ResetZoom => {
+                let viewport = &mut self.state.document_mut().viewport;
+                viewport.zoom_around(Viewport::DEFAULT_CELL_SIZE, viewport.center());
+                Task::none()
+            },
+            Message::FitToDrawing => {
+                if let Some((min, max)) = self.state.document().layers.bounding_box() {
+                    self.state.document_mut().viewport.fit(min, max);
                 }
                 Task::none()
             },
-            Message::ZoomIn => {
-                if self.state.cell_size <= 10.0 {
-                    self.state.cell_size += 1.0;
+            Message::MoveViewport(x, y) => {
+                self.state.document_mut().viewport.top_left = (x, y);
+                Task::none()
+            },
+            Message::PanBy(dx, dy) => {
+                self.state.document_mut().viewport.pan(dx, dy);
+                Task::none()
+            },
+            Message::ArrowKeyPressed(dx, dy) => {
+                // Arrows drive the keyboard cursor while it's shown, and pan
+                // the view otherwise
+                if self.state.keyboard_cursor.is_some() {
+                    self.update(Message::MoveKeyboardCursor(dx, dy))
                 } else {
-                    self.state.cell_size *= 1.1;
+                    let step = ARROW_PAN_CELLS * self.state.document().viewport.cell_size;
+                    self.state.document_mut().viewport.pan(dx as f32 * step, dy as f32 * step);
+                    Task::none()
                 }
-                self.state.cell_size = self.state.cell_size.floor().min(100.0);
+            },
+            Message::GoToOrigin => {
+                self.state.document_mut().viewport.go_to_origin();
                 Task::none()
             },
-            Message::ZoomOut => {
-                self.state.cell_size /= 1.1;
-                self.state.cell_size = self.state.cell_size.floor().max(5.0);
+            Message::CanvasResized(width, height) => {
+                self.state.document_mut().viewport.size = (width, height);
                 Task::none()
             },
             Message::ToggleGridVisibility => {
                 self.state.grid_visible = !self.state.grid_visible;
                 Task::none()
             },
+            Message::ToggleMinimapVisibility => {
+                self.state.minimap_visible = !self.state.minimap_visible;
+                Task::none()
+            },
+            Message::ToggleRulersVisibility => {
+                self.state.rulers_visible = !self.state.rulers_visible;
+                Task::none()
+            },
+            Message::ChangeMajorGridSpacing(change) => {
+                self.state.major_grid_spacing = (self.state.major_grid_spacing + change).max(1);
+                Task::none()
+            },
+            Message::SelectSymmetry(mode) => {
+                self.state.symmetry.mode = mode;
+                Task::none()
+            },
+            Message::SetSymmetryCenter => {
+                if let Some(cell) = self.state.hovered_cell {
+                    self.state.symmetry.center = cell;
+                }
+                Task::none()
+            },
+            Message::ToggleSymmetryThroughCells => {
+                self.state.symmetry.through_cells = !self.state.symmetry.through_cells;
+                Task::none()
+            },
+            Message::SelectPlacement(placement) => {
+                self.state.placement = placement;
+                self.state.baseline = None;
+                Task::none()
+            },
+            Message::ChangeLatticePitch(change) => {
+                self.state.lattice.pitch = (self.state.lattice.pitch + change).max(1);
+                Task::none()
+            },
+            Message::ChangeLatticeGutter(change) => {
+                self.state.lattice.gutter = (self.state.lattice.gutter + change).max(0);
+                Task::none()
+            },
+            Message::SetLatticeOrigin => {
+                if let Some(cell) = self.state.hovered_cell {
+                    self.state.lattice.origin = cell;
+                    self.state.baseline = None;
+                }
+                Task::none()
+            },
+            Message::NextTextLine => {
+                let lattice = self.state.lattice;
+                let line = self.state.baseline
+                    .or(self.state.hovered_cell.map(|cell| lattice.slot(cell).1))
+                    .unwrap_or(lattice.origin.1);
+                self.state.baseline = Some(line + lattice.step());
+                Task::none()
+            },
+            Message::MoveGuide(axis, index, to) => {
+                self.state.document_mut().guides.move_guide(axis, index, to);
+                Task::none()
+            },
+            Message::RemoveGuide(axis, index) => {
+                self.state.document_mut().guides.remove(axis, index);
+                Task::none()
+            },
+            Message::NewDocument => {
+                self.state.new_document();
+                Task::none()
+            },
+            Message::CloseDocument(index) => {
+                if self.state.documents.get(index).is_some_and(|document| document.undo_history.is_dirty()) {
+                    self.confirmation = Some(Confirmation::CloseDocument(index));
+                } else {
+                    self.state.close_document(index);
+                }
+                Task::none()
+            },
+            Message::Quit => {
+                // Quitting before answering keeps the recovery file for the
+                // next session
+                if matches!(self.confirmation, Some(Confirmation::Recover(_))) {
+                    return iced::exit();
+                }
+                if self.state.documents.iter().any(|document| document.undo_history.is_dirty()) {
+                    self.confirmation = Some(Confirmation::Quit);
+                    Task::none()
+                } else {
+                    self.autosave();
+                    iced::exit()
+                }
+            },
+            Message::Confirm => {
+                match self.confirmation.take() {
+                    Some(Confirmation::CloseDocument(index)) => self.state.close_document(index),
+                    Some(Confirmation::Quit) => {
+                        // Quitting on purpose leaves nothing to recover
+                        let _ = std::fs::remove_file(RECOVERY_PATH);
+                        return iced::exit();
+                    },
+                    Some(Confirmation::Recover(documents)) => {
+                        // An untouched empty document is replaced rather than kept
+                        let first = &self.state.documents[0];
+                        if self.state.documents.len() == 1
+                            && first.undo_history.entries().is_empty()
+                            && first.layers.bounding_box().is_none() {
+                            self.state.documents.clear();
+                        }
+                        let index = self.state.documents.len();
+                        let limits = self.state.history_limits;
+                        self.state.documents.extend(documents.into_iter().map(|mut document| {
+                            document.undo_history.set_limits(limits);
+                            document
+                        }));
+                        self.state.select_document(index);
+                    },
+                    None => {},
+                }
+                Task::none()
+            },
+            Message::DiscardRecovery => {
+                self.confirmation = None;
+                let _ = std::fs::remove_file(RECOVERY_PATH);
+                Task::none()
+            },
+            Message::Autosave => {
+                self.autosave();
+                Task::none()
+            },
+            Message::SelectDocument(index) => {
+                self.state.select_document(index);
+                Task::none()
+            },
+            Message::NextDocument => {
+                let next = (self.state.active_document + 1) % self.state.documents.len();
+                self.state.select_document(next);
+                Task::none()
+            },
+            Message::CopySelection => {
+                let Some((grid, size)) = self.copied_cells() else {
+                    return Task::none();
+                };
+                // The text comes along as the plain version of the image
+                let text = to_clipboard_text(&grid, (0, 0), size);
+                let result = to_clipboard_html(&grid, (0, 0), size).and_then(|html| {
+                    let clipboard = self.system_clipboard.as_mut().ok_or("no clipboard to connect to")?;
+                    clipboard.set().html(html, Some(text)).map_err(|error| error.to_string())
+                });
+                if let Err(error) = result {
+                    self.status = Some(format!("Copied in the editor only: {error}"));
+                }
+                self.state.clipboard = Some(grid);
+                Task::none()
+            },
+            Message::Paste => {
+                // Images come first, since applications often put the image's
+                // name on the clipboard as text too
+                let pasted = self.system_clipboard.as_mut().and_then(|clipboard| {
+                    clipboard.get_image()
+                        .ok()
+                        .and_then(|image| {
+                            image::RgbaImage::from_raw(image.width as u32, image.height as u32, image.bytes.into_owned())
+                        })
+                        .and_then(from_clipboard_image)
+                        .or_else(|| clipboard.get_text().ok().as_deref().and_then(from_clipboard_text))
+                });
+                if let Some(grid) = pasted.or_else(|| self.state.clipboard.clone()) {
+                    self.float(grid);
+                }
+                Task::none()
+            },
+            Message::AddLayer => {
+                let document = self.state.document_mut();
+                let id = document.layers.add();
+                document.undo_history.push(format!("Add Layer {id}"), Action::DeleteLayer(id));
+                Task::none()
+            },
+            Message::RemoveLayer => {
+                let layer = self.state.document().layers.active();
+                self.change_layers(format!("Remove {}", layer.name), Action::DeleteLayer(layer.id));
+                Task::none()
+            },
+            Message::SelectLayer(index) => {
+                self.state.document_mut().layers.select(index);
+                Task::none()
+            },
+            Message::MoveLayer(offset) => {
+                let layers = &self.state.document().layers;
+                let target = layers.active_index().saturating_add_signed(offset).min(layers.len() - 1);
+                if target != layers.active_index() {
+                    let layer = layers.active();
+                    let label = format!("Move {} {}", layer.name, if offset > 0 { "up" } else { "down" });
+                    self.change_layers(label, Action::MoveLayer(layer.id, target));
+                }
+                Task::none()
+            },
+            Message::ToggleLayerVisibility(index) => {
+                self.change_layer_settings(index, |settings| {
+                    settings.visible = !settings.visible;
+                    if settings.visible { "Show" } else { "Hide" }
+                });
+                Task::none()
+            },
+            Message::ToggleLayerLock(index) => {
+                self.change_layer_settings(index, |settings| {
+                    settings.locked = !settings.locked;
+                    if settings.locked { "Lock" } else { "Unlock" }
+                });
+                Task::none()
+            },
+            Message::LayerOpacityChanged(index, opacity) => {
+                self.change_layer_settings(index, |settings| {
+                    settings.opacity = opacity;
+                    "Set opacity of"
+                });
+                Task::none()
+            },
             Message::Undo => {
-                if let Some(action) = self.state.undo_history.pop() {
-                    match action {
-                        Action::Paint(cells) => {
-                            for (point, color) in cells {
-                                let (x, y) = point;
-                                self.state.grid.set(x, y, color);
-                            }
-                        },
-                    }
+                let document = self.state.document_mut();
+                document.undo_history.undo(&mut document.layers);
+                Task::none()
+            },
+            Message::Redo => {
+                let document = self.state.document_mut();
+                document.undo_history.redo(&mut document.layers);
+                Task::none()
+            },
+            Message::JumpToHistory(position) => {
+                let document = self.state.document_mut();
+                document.undo_history.jump_to(position, &mut document.layers);
+                Task::none()
+            },
+            Message::OpenExportDialog => {
+                self.export_dialog = Some(ExportDialog::new(&self.state.document().path, &self.state.export_options));
+                Task::none()
+            },
+            Message::CloseExportDialog => {
+                self.export_dialog = None;
+                Task::none()
+            },
+            Message::ExportPathChanged(_) |
+            Message::ExportScaleChanged(_) |
+            Message::ExportMarginChanged(_) |
+            Message::ExportSizeChanged(_) |
+            Message::ExportFormatSelected(_) |
+            Message::ExportForegroundChanged(_) |
+            Message::ExportBackgroundChanged(_) |
+            Message::ExportTransparentToggled(_) |
+            Message::ExportGridLinesToggled(_) |
+            Message::ExportPageSizeSelected(_) |
+            Message::ExportCellSizeChanged(_) |
+            Message::ExportRegistrationMarksToggled(_) |
+            Message::ExportNumbersToggled(_) |
+            Message::ExportAnimateToggled(_) |
+            Message::ExportPacingSelected(_) |
+            Message::ExportFrameDelayChanged(_) => {
+                if let Some(dialog) = &mut self.export_dialog {
+                    dialog.update(message);
                 }
                 Task::none()
             },
             Message::ExportImage => {
-                let img = self.state.grid.render(10);
-                img.save("export.png").expect("Failed to save image");
+                if let Some(dialog) = &mut self.export_dialog && !dialog.needs_confirmation() {
+                    self.export();
+                }
+                Task::none()
+            },
+            Message::ConfirmOverwrite => {
+                self.export();
                 Task::none()
             },
         }
     }
     
     fn subscription(&self) -> iced::Subscription<Message> {
-        keyboard::on_key_press(|key, _modifiers| {
+        iced::Subscription::batch([
+            iced::time::every(AUTOSAVE_INTERVAL).map(|_| Message::Autosave),
+            iced::window::close_requests().map(|_| Message::Quit),
+            // Buttons released outside the window never reach the canvas, so
+            // strokes also end when the mouse leaves the window or it loses
+            // focus
+            iced::event::listen_with(|event, _status, _window| match event {
+                iced::Event::Window(iced::window::Event::Unfocused)
+                | iced::Event::Mouse(iced::mouse::Event::CursorLeft) => Some(Message::MouseReleased),
+                _ => None,
+            }),
+            self.key_bindings(),
+        ])
+    }
+
+    fn key_bindings(&self) -> iced::Subscription<Message> {
+        keyboard::on_key_press(|key, modifiers| {
+            if modifiers.command() {
+                return match key.as_ref() {
+                    keyboard::Key::Character("c") => Some(Message::CopySelection),
+                    keyboard::Key::Character("z") => Some(Message::Undo),
+                    keyboard::Key::Character("y" | "r") => Some(Message::Redo),
+                    keyboard::Key::Character("v") => Some(Message::Paste),
+                    keyboard::Key::Character("n" | "t") => Some(Message::NewDocument),
+                    keyboard::Key::Named(keyboard::key::Named::Tab) => Some(Message::NextDocument),
+                    _ => None,
+                };
+            }
             match key.as_ref() {
                 keyboard::Key::Character("/") => Some(Message::FocusSearchInput),
                 keyboard::Key::Character("g") => Some(Message::ToggleGridVisibility),
-                keyboard::Key::Named(keyboard::key::Named::Escape) => Some(Message::UnselectAtom),
+                keyboard::Key::Character("m") => Some(Message::ToggleMinimapVisibility),
+                keyboard::Key::Character("r") => Some(Message::ToggleRulersVisibility),
+                keyboard::Key::Character("a") => Some(Message::SetLatticeOrigin),
+                keyboard::Key::Character("c") => Some(Message::SetSymmetryCenter),
+                keyboard::Key::Character("n") => Some(Message::NextTextLine),
+                keyboard::Key::Named(keyboard::key::Named::Escape) => Some(Message::Cancel),
                 keyboard::Key::Character("u") => Some(Message::Undo),
-                keyboard::Key::Character("x") => Some(Message::ExportImage),
+                keyboard::Key::Character("x") => Some(Message::OpenExportDialog),
+                keyboard::Key::Character("p") => Some(Message::SelectTool(Tool::Pencil)),
+                keyboard::Key::Character("e") => Some(Message::SelectTool(Tool::Eraser)),
+                keyboard::Key::Character("t") => Some(Message::SelectTool(Tool::Toggle)),
+                keyboard::Key::Character("s") => Some(Message::SelectTool(Tool::Select)),
+                keyboard::Key::Character("]") => Some(Message::GrowBrush),
+                keyboard::Key::Character("[") => Some(Message::ShrinkBrush),
+                keyboard::Key::Character("b") => Some(Message::ToggleBrushShape),
+                keyboard::Key::Character("+" | "=") => Some(Message::ZoomInAtCenter),
+                keyboard::Key::Character("-") => Some(Message::ZoomOutAtCenter),
+                keyboard::Key::Character("0") => Some(Message::ResetZoom),
+                keyboard::Key::Character("f") => Some(Message::FitToDrawing),
+                keyboard::Key::Character("h") => Some(Message::MoveKeyboardCursor(-1, 0)),
+                keyboard::Key::Character("j") => Some(Message::MoveKeyboardCursor(0, 1)),
+                keyboard::Key::Character("k") => Some(Message::MoveKeyboardCursor(0, -1)),
+                keyboard::Key::Character("l") => Some(Message::MoveKeyboardCursor(1, 0)),
+                keyboard::Key::Named(keyboard::key::Named::ArrowLeft) => Some(Message::ArrowKeyPressed(-1, 0)),
+                keyboard::Key::Named(keyboard::key::Named::ArrowDown) => Some(Message::ArrowKeyPressed(0, 1)),
+                keyboard::Key::Named(keyboard::key::Named::ArrowUp) => Some(Message::ArrowKeyPressed(0, -1)),
+                keyboard::Key::Named(keyboard::key::Named::ArrowRight) => Some(Message::ArrowKeyPressed(1, 0)),
+                keyboard::Key::Character("o") => Some(Message::GoToOrigin),
+                // Space only pans the canvas while the keyboard cursor is hidden
+                keyboard::Key::Named(keyboard::key::Named::Space | keyboard::key::Named::Enter) =>
+                    Some(Message::KeyboardCursorPressed),
                 _ => None,
             }
         })
     }
 }
 
+// Whether a message can come in the middle of a stroke without ending it:
+// those that only move the view or change the brush
+fn continues_stroke(message: &Message) -> bool {
+    matches!(
+        message,
+        Message::CursorMovedToCell(..)
+            | Message::MouseReleased
+            | Message::GrowBrush
+            | Message::ShrinkBrush
+            | Message::ToggleBrushShape
+            | Message::ZoomIn(_)
+            | Message::ZoomOut(_)
+            | Message::ZoomBy(..)
+            | Message::ZoomInAtCenter
+            | Message::ZoomOutAtCenter
+            | Message::ResetZoom
+            | Message::FitToDrawing
+            | Message::MoveViewport(..)
+            | Message::PanBy(..)
+            | Message::ArrowKeyPressed(..)
+            | Message::GoToOrigin
+            | Message::CanvasResized(..)
+            | Message::ToggleGridVisibility
+            | Message::ToggleMinimapVisibility
+            | Message::ToggleRulersVisibility
+            | Message::ChangeMajorGridSpacing(_)
+            | Message::SetLatticeOrigin
+            | Message::Autosave
+    )
+}
+
 fn import_csv() -> Vec<Atom> {
     // TODO: Let the user choose the file to import
     // Also let the user choose if the CSV has headers
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .flexible(true)
-        .from_path("data.csv")
-        .expect("Cannot open CSV file");
-    reader
-        .records()
-        .map(|result| {
-            let record = result.expect("Error reading CSV record");
-            Atom::from_csv_record(&record)
-        })
-        .collect()
+    pixel_editor::read_atoms(Path::new("data.csv")).expect("Cannot read CSV file")
 }
 
-
 fn main() -> iced::Result {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|command| command == "render") {
+        if let Err(error) = cli::render(&args[1..]) {
+            eprintln!("{error}\n\n{}", cli::USAGE);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let limits = match cli::history_limits(&args) {
+        Ok(limits) => limits,
+        Err(error) => {
+            eprintln!("{error}\n\n{}", cli::EDITOR_USAGE);
+            std::process::exit(1);
+        },
+    };
     iced::application("Pixel Editor", App::update, App::view)
         .subscription(App::subscription)
-        .run()
+        .exit_on_close_request(false)
+        .run_with(move || {
+            let mut app = App::default();
+            app.state.set_history_limits(limits);
+            (app, Task::none())
+        })
 }
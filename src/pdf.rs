@@ -0,0 +1,250 @@
+use std::fmt::Write;
+use image::Rgba;
+
+use crate::{Color, ExportOptions, Grid, GridIndex, GridPoint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    pub const ALL: [PageSize; 2] = [PageSize::A4, PageSize::Letter];
+
+    // Width and height of the portrait page, in millimetres
+    pub fn size_mm(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+impl std::fmt::Display for PageSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PageSize::A4 => write!(f, "A4"),
+            PageSize::Letter => write!(f, "Letter"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrintOptions {
+    pub page_size: PageSize,
+    // Side of each cell on paper
+    pub cell_mm: f32,
+    // Crosses around each page's cells to line the pages up
+    pub registration_marks: bool,
+    // Row and column numbers along the edges, counted from the first
+    // exported cell
+    pub numbers: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::A4,
+            cell_mm: 5.0,
+            registration_marks: true,
+            numbers: true,
+        }
+    }
+}
+
+const POINTS_PER_MM: f32 = 72.0 / 25.4;
+const PAGE_MARGIN_MM: f32 = 12.0;
+// Room left for the row and column numbers, when shown
+const NUMBERS_MM: f32 = 6.0;
+const MARK_MM: f32 = 4.0;
+const FONT_SIZE: f32 = 6.0;
+
+// Content of a single page, in points from the bottom left corner
+struct Page {
+    content: String,
+    height: f32,
+}
+
+impl Page {
+    fn color(&mut self, color: Rgba<u8>, operator: &str) {
+        let [r, g, b, _] = color.0;
+        let _ = writeln!(
+            self.content,
+            "{:.3} {:.3} {:.3} {operator}",
+            r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0,
+        );
+    }
+
+    // Rectangle given by its top left corner, with y growing downwards
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32) {
+        let _ = writeln!(self.content, "{x:.2} {:.2} {width:.2} {height:.2} re f", self.height - y - height);
+    }
+
+    fn line(&mut self, (x1, y1): (f32, f32), (x2, y2): (f32, f32)) {
+        let _ = writeln!(
+            self.content,
+            "{x1:.2} {:.2} m {x2:.2} {:.2} l S",
+            self.height - y1, self.height - y2,
+        );
+    }
+
+    // Text whose baseline starts at the given point
+    fn text(&mut self, x: f32, y: f32, text: &str) {
+        let _ = writeln!(
+            self.content,
+            "BT /F1 {FONT_SIZE} Tf {x:.2} {:.2} Td ({text}) Tj ET",
+            self.height - y,
+        );
+    }
+}
+
+// Lays the exported cells out over as many pages as needed, at the print
+// options' cell size, and writes them as a PDF document
+pub fn render_pdf(grid: &Grid<Color>, options: &ExportOptions) -> Vec<u8> {
+    let print = &options.print;
+    let ((min_x, min_y), (max_x, max_y)) = options.bounds(grid.bounding_box());
+    let (page_width, page_height) = print.page_size.size_mm();
+    let numbers_mm = if print.numbers { NUMBERS_MM } else { 0.0 };
+    let usable_width = page_width - 2.0 * PAGE_MARGIN_MM - numbers_mm;
+    let usable_height = page_height - 2.0 * PAGE_MARGIN_MM - numbers_mm;
+    let columns_per_page = ((usable_width / print.cell_mm).floor() as GridIndex).max(1);
+    let rows_per_page = ((usable_height / print.cell_mm).floor() as GridIndex).max(1);
+
+    let mut pages = Vec::new();
+    let mut top = min_y;
+    while top <= max_y {
+        let mut left = min_x;
+        while left <= max_x {
+            let right = (left + columns_per_page - 1).min(max_x);
+            let bottom = (top + rows_per_page - 1).min(max_y);
+            pages.push(render_page(grid, options, (min_x, min_y), (left, top), (right, bottom)));
+            left += columns_per_page;
+        }
+        top += rows_per_page;
+    }
+    write_document(&pages, (page_width * POINTS_PER_MM, page_height * POINTS_PER_MM))
+}
+
+// Page with the cells inside the given corners (both inclusive). `origin`
+// is the first exported cell, which the numbers count from.
+fn render_page(grid: &Grid<Color>, options: &ExportOptions, origin: GridPoint, (left, top): GridPoint, (right, bottom): GridPoint) -> Page {
+    let print = &options.print;
+    let (_, page_height) = print.page_size.size_mm();
+    let mut page = Page { content: String::new(), height: page_height * POINTS_PER_MM };
+    let cell = print.cell_mm * POINTS_PER_MM;
+    let numbers = if print.numbers { NUMBERS_MM * POINTS_PER_MM } else { 0.0 };
+    let x0 = PAGE_MARGIN_MM * POINTS_PER_MM + numbers;
+    let y0 = PAGE_MARGIN_MM * POINTS_PER_MM + numbers;
+    let width = (right - left + 1) as f32 * cell;
+    let height = (bottom - top + 1) as f32 * cell;
+
+    if !options.transparent {
+        page.color(options.background, "rg");
+        page.fill_rect(x0, y0, width, height);
+    }
+    page.color(options.foreground, "rg");
+    for run in grid.runs((left, top), (right, bottom)) {
+        if run.value == Color::Black {
+            page.fill_rect(
+                x0 + (run.start.0 - left) as f32 * cell,
+                y0 + (run.start.1 - top) as f32 * cell,
+                run.length as f32 * cell,
+                cell,
+            );
+        }
+    }
+
+    let _ = writeln!(page.content, "0.3 w");
+    if options.grid_lines {
+        page.color(Rgba([160, 160, 160, 255]), "RG");
+        for i in 0..=(right - left + 1) {
+            let x = x0 + i as f32 * cell;
+            page.line((x, y0), (x, y0 + height));
+        }
+        for i in 0..=(bottom - top + 1) {
+            let y = y0 + i as f32 * cell;
+            page.line((x0, y), (x0 + width, y));
+        }
+    }
+
+    page.color(Rgba([0, 0, 0, 255]), "rg");
+    page.color(Rgba([0, 0, 0, 255]), "RG");
+    if print.numbers {
+        // Small cells only get every fifth number so they don't overlap
+        let step = if print.cell_mm < 4.0 { 5 } else { 1 };
+        for x in left..=right {
+            let number = x - origin.0 + 1;
+            if number % step == 0 {
+                let text_x = x0 + (x - left) as f32 * cell + 0.5;
+                page.text(text_x, y0 - 2.0, &number.to_string());
+            }
+        }
+        for y in top..=bottom {
+            let number = y - origin.1 + 1;
+            if number % step == 0 {
+                let text_y = y0 + (y - top + 1) as f32 * cell - 1.0;
+                page.text(x0 - numbers + 1.0, text_y, &number.to_string());
+            }
+        }
+    }
+
+    if print.registration_marks {
+        let mark = MARK_MM * POINTS_PER_MM;
+        let corners = [(x0, y0), (x0 + width, y0), (x0, y0 + height), (x0 + width, y0 + height)];
+        for (x, y) in corners {
+            page.line((x - mark, y), (x + mark, y));
+            page.line((x, y - mark), (x, y + mark));
+        }
+    }
+
+    page.text(x0, y0 + height + 12.0, &format!(
+        "Columns {}-{}, rows {}-{}",
+        left - origin.0 + 1, right - origin.0 + 1, top - origin.1 + 1, bottom - origin.1 + 1,
+    ));
+    page
+}
+
+fn write_document(pages: &[Page], (width, height): (f32, f32)) -> Vec<u8> {
+    // Objects 1 to 3 are the catalog, the page tree and the font, then each
+    // page takes two objects: itself and its content stream
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len()).map(|i| format!("{} 0 R", 4 + 2 * i)).collect::<Vec<_>>().join(" "),
+            pages.len(),
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+    ];
+    for (i, page) in pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {width:.2} {height:.2}] \
+             /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            5 + 2 * i,
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            page.content.len(),
+            page.content,
+        ));
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n{object}\nendobj\n", i + 1).bytes());
+    }
+    let xref = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+    for offset in offsets {
+        pdf.extend(format!("{offset:010} 00000 n \n").bytes());
+    }
+    pdf.extend(format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1,
+    ).bytes());
+    pdf
+}
@@ -1,8 +1,10 @@
 use iced::Point;
 use iced::widget::canvas;
-use iced::{mouse, event};
+use iced::{keyboard, mouse, event};
 
-use pixel_editor::{Color, GridIndex, Message, ProgramState};
+use pixel_editor::{Atom, Axis, Color, GridIndex, GridPoint, Message, PaintButton, Placement, ProgramState, SymmetryMode, Viewport};
+use std::cell::Cell;
+use std::collections::HashSet;
 
 pub struct PixelCanvas<'a> {
     program_state: &'a ProgramState,
@@ -12,15 +14,241 @@ impl<'a> PixelCanvas<'a> {
     pub fn new(program_state: &'a ProgramState) -> Self {
         Self { program_state }
     }
+
+    // Guide to drag after a press on the rulers at `(x, y)`, relative to the
+    // canvas. The top ruler grabs the vertical guide under the press, or else
+    // pulls out a new horizontal one, and the left ruler the other way around.
+    fn grab_guide(&self, (x, y): (f32, f32)) -> Option<(Axis, Option<usize>)> {
+        let viewport = &self.program_state.document().viewport;
+        let guides = &self.program_state.document().guides;
+        let grabbed = |axis, position: f32, offset: f32| {
+            guides
+                .lines(axis)
+                .iter()
+                .position(|&line| (line as f32 * viewport.cell_size - offset - position).abs() <= GUIDE_GRAB_DISTANCE)
+        };
+        if x < RULER_SIZE && y < RULER_SIZE {
+            // The corner where the rulers meet
+            None
+        } else if y < RULER_SIZE {
+            match grabbed(Axis::Vertical, x, viewport.top_left.0) {
+                Some(index) => Some((Axis::Vertical, Some(index))),
+                None => Some((Axis::Horizontal, None)),
+            }
+        } else {
+            match grabbed(Axis::Horizontal, y, viewport.top_left.1) {
+                Some(index) => Some((Axis::Horizontal, Some(index))),
+                None => Some((Axis::Vertical, None)),
+            }
+        }
+    }
+
+    // Draws the rulers along the top and left edges, numbered in cells every
+    // 1, 2 or 5 times a power of ten, with the hovered cell highlighted
+    fn draw_rulers(&self, frame: &mut canvas::Frame, size: iced::Size, hovered: GridPoint) {
+        let viewport = &self.program_state.document().viewport;
+        let cell_size = viewport.cell_size;
+        // Goes 1, 2, 5, 10, 20, 50... until the labels fit
+        let mut step: GridIndex = 1;
+        let mut power_of_ten = 1;
+        while (step as f32) * cell_size < RULER_LABEL_SPACING {
+            step = match step / power_of_ten {
+                1 => 2 * power_of_ten,
+                2 => 5 * power_of_ten,
+                _ => {
+                    power_of_ten *= 10;
+                    power_of_ten
+                },
+            };
+        }
+        let background = iced::Color { a: 0.95, ..iced::Color::WHITE };
+        let highlight = iced::Color::from_rgba(0.0, 0.4, 0.9, 0.3);
+        frame.fill_rectangle(Point::ORIGIN, iced::Size::new(size.width, RULER_SIZE), background);
+        frame.fill_rectangle(Point::ORIGIN, iced::Size::new(RULER_SIZE, size.height), background);
+        frame.fill_rectangle(
+            Point::new(hovered.0 as f32 * cell_size - viewport.top_left.0, 0.0),
+            iced::Size::new(cell_size, RULER_SIZE),
+            highlight,
+        );
+        frame.fill_rectangle(
+            Point::new(0.0, hovered.1 as f32 * cell_size - viewport.top_left.1),
+            iced::Size::new(RULER_SIZE, cell_size),
+            highlight,
+        );
+
+        let mut labels = Vec::new();
+        let ticks = canvas::Path::new(|builder| {
+            builder.move_to(Point::new(0.0, RULER_SIZE));
+            builder.line_to(Point::new(size.width, RULER_SIZE));
+            builder.move_to(Point::new(RULER_SIZE, 0.0));
+            builder.line_to(Point::new(RULER_SIZE, size.height));
+            let first_column = (viewport.top_left.0 / cell_size).floor() as GridIndex;
+            for column in first_column..=first_column + (size.width / cell_size) as GridIndex + 1 {
+                let x = column as f32 * cell_size - viewport.top_left.0;
+                if x < RULER_SIZE {
+                    continue;
+                }
+                if column.rem_euclid(step) == 0 {
+                    builder.move_to(Point::new(x, 0.0));
+                    labels.push((column, Point::new(x + 2.0, 1.0)));
+                } else if step <= 5 {
+                    builder.move_to(Point::new(x, RULER_SIZE * 0.7));
+                } else {
+                    continue;
+                }
+                builder.line_to(Point::new(x, RULER_SIZE));
+            }
+            let first_row = (viewport.top_left.1 / cell_size).floor() as GridIndex;
+            for row in first_row..=first_row + (size.height / cell_size) as GridIndex + 1 {
+                let y = row as f32 * cell_size - viewport.top_left.1;
+                if y < RULER_SIZE {
+                    continue;
+                }
+                if row.rem_euclid(step) == 0 {
+                    builder.move_to(Point::new(0.0, y));
+                    labels.push((row, Point::new(1.0, y + 1.0)));
+                } else if step <= 5 {
+                    builder.move_to(Point::new(RULER_SIZE * 0.7, y));
+                } else {
+                    continue;
+                }
+                builder.line_to(Point::new(RULER_SIZE, y));
+            }
+        });
+        frame.stroke(&ticks, canvas::Stroke::default().with_width(1.0));
+        for (number, position) in labels {
+            frame.fill_text(canvas::Text {
+                content: number.to_string(),
+                position,
+                size: iced::Pixels(9.0),
+                ..canvas::Text::default()
+            });
+        }
+    }
+
+    // The minimap covers the drawing and the visible part of the canvas,
+    // scaled down to fit in the bottom right corner
+    fn minimap(&self, canvas_size: iced::Size) -> Minimap {
+        let viewport = &self.program_state.document().viewport;
+        let mut min = Point::new(
+            viewport.top_left.0 / viewport.cell_size,
+            viewport.top_left.1 / viewport.cell_size,
+        );
+        let mut max = Point::new(
+            (viewport.top_left.0 + canvas_size.width) / viewport.cell_size,
+            (viewport.top_left.1 + canvas_size.height) / viewport.cell_size,
+        );
+        if let Some(((min_x, min_y), (max_x, max_y))) = self.program_state.document().layers.bounding_box() {
+            min.x = min.x.min(min_x as f32);
+            min.y = min.y.min(min_y as f32);
+            max.x = max.x.max((max_x + 1) as f32);
+            max.y = max.y.max((max_y + 1) as f32);
+        }
+        let region = iced::Size::new(max.x - min.x + 2.0 * MINIMAP_PADDING, max.y - min.y + 2.0 * MINIMAP_PADDING);
+        let scale = (MINIMAP_MAX_SIDE / region.width).min(MINIMAP_MAX_SIDE / region.height);
+        let size = iced::Size::new(region.width * scale, region.height * scale);
+        Minimap {
+            bounds: iced::Rectangle::new(
+                Point::new(
+                    canvas_size.width - size.width - MINIMAP_MARGIN,
+                    canvas_size.height - size.height - MINIMAP_MARGIN,
+                ),
+                size,
+            ),
+            origin: Point::new(min.x - MINIMAP_PADDING, min.y - MINIMAP_PADDING),
+            scale,
+        }
+    }
 }
 
+// Layout of the minimap, relative to the canvas
+struct Minimap {
+    bounds: iced::Rectangle,
+    // Cell coordinates shown at the top left corner of the minimap
+    origin: Point,
+    // Minimap pixels per cell
+    scale: f32,
+}
+
+impl Minimap {
+    fn to_minimap(&self, cell_x: f32, cell_y: f32) -> Point {
+        Point::new(
+            self.bounds.x + (cell_x - self.origin.x) * self.scale,
+            self.bounds.y + (cell_y - self.origin.y) * self.scale,
+        )
+    }
+
+    fn to_cells(&self, position: Point) -> Point {
+        Point::new(
+            self.origin.x + (position.x - self.bounds.x) / self.scale,
+            self.origin.y + (position.y - self.bounds.y) / self.scale,
+        )
+    }
+}
+
+// Thickness of the rulers along the top and left edges
+const RULER_SIZE: f32 = 20.0;
+// Smallest distance between numbered ruler ticks
+const RULER_LABEL_SPACING: f32 = 40.0;
+// How close to a guide, in pixels, a press on a ruler grabs it
+const GUIDE_GRAB_DISTANCE: f32 = 4.0;
+
+const MINIMAP_MAX_SIDE: f32 = 150.0;
+const MINIMAP_MARGIN: f32 = 10.0;
+// Cells of empty space around the minimap's contents
+const MINIMAP_PADDING: f32 = 2.0;
+
 #[derive(Default)]
 pub struct CanvasState {
     mouse_pos: Point,
-    top_left: Point,
-    middle_button_start: Option<Point>,
-    middle_button_top_left_start: Option<Point>,
+    // Where the mouse and the view were when a middle or space drag started
+    pan_start: Option<Point>,
+    pan_top_left_start: Option<Point>,
+    space_held: bool,
+    dragging_minimap: bool,
+    // Guide being dragged out of a ruler, with its index unless it's new
+    dragging_guide: Option<(Axis, Option<usize>)>,
+    modifiers: keyboard::Modifiers,
     last_cell: Option<(GridIndex, GridIndex)>,
+    cells_cache: canvas::Cache,
+    grid_lines_cache: canvas::Cache,
+    minimap_cache: canvas::Cache,
+    cached_for: Cell<Option<CacheKey>>,
+    // The minimap's cells are cached apart from the view's rectangle on it,
+    // so panning and zooming don't draw them again
+    minimap_cells_cache: canvas::Cache,
+    minimap_cached_for: Cell<Option<MinimapKey>>,
+}
+
+// What the cached layers were drawn for
+#[derive(Clone, Copy, PartialEq)]
+struct CacheKey {
+    revision: (u64, u64),
+    viewport: Viewport,
+    bounds: iced::Size,
+    grid_visible: bool,
+    minimap_visible: bool,
+    major_grid_spacing: GridIndex,
+}
+
+// What the minimap's cells were drawn for
+#[derive(Clone, Copy, PartialEq)]
+struct MinimapKey {
+    revision: (u64, u64),
+    visible: bool,
+    bounds: iced::Rectangle,
+    origin: Point,
+    scale: f32,
+}
+
+// Scrolled pixels that amount to a whole zoom step, for trackpads
+const PIXELS_PER_ZOOM_STEP: f32 = 50.0;
+
+impl CanvasState {
+    fn start_pan(&mut self, top_left: Point) {
+        self.pan_start = Some(self.mouse_pos);
+        self.pan_top_left_start = Some(top_left);
+    }
 }
 
 impl<'a> canvas::Program<Message> for PixelCanvas<'a> {
@@ -34,18 +262,48 @@ impl<'a> canvas::Program<Message> for PixelCanvas<'a> {
         bounds: iced::Rectangle,
         _cursor: mouse::Cursor,
     ) -> (event::Status, Option<Message>) {
-        let x: GridIndex = ((state.mouse_pos.x - bounds.x + state.top_left.x) / self.program_state.cell_size).floor() as GridIndex;
-        let y: GridIndex = ((state.mouse_pos.y - bounds.y + state.top_left.y) / self.program_state.cell_size).floor() as GridIndex;
+        let viewport = &self.program_state.document().viewport;
+        let top_left = Point::new(viewport.top_left.0, viewport.top_left.1);
+        // Mouse position relative to the canvas, to anchor zooming
+        let anchor = (state.mouse_pos.x - bounds.x, state.mouse_pos.y - bounds.y);
+        let (x, y) = viewport.cell_at(anchor);
+        let minimap = self.minimap(bounds.size());
+        // Centers the view on the part of the minimap under the mouse
+        let jump_to_minimap = |viewport: &Viewport| {
+            let center = minimap.to_cells(Point::new(anchor.0, anchor.1));
+            Message::MoveViewport(
+                center.x * viewport.cell_size - bounds.width / 2.0,
+                center.y * viewport.cell_size - bounds.height / 2.0,
+            )
+        };
         match event {
             canvas::Event::Mouse(e) => {
                 match e {
                     mouse::Event::CursorMoved{position} => {
                         state.mouse_pos = position;
-                        if let Some(start) = state.middle_button_start {
-                            let middle_button_start = state.middle_button_top_left_start
-                                .expect("Middle button start position should be set if middle button is held"); 
-                            state.top_left.x = middle_button_start.x - (state.mouse_pos.x - start.x);
-                            state.top_left.y = middle_button_start.y - (state.mouse_pos.y - start.y);
+                        // The app needs the canvas size to zoom around its center
+                        // and fit the drawing, and cursor moves are frequent enough
+                        // to keep it up to date
+                        if (bounds.width, bounds.height) != viewport.size {
+                            return (event::Status::Captured, Some(Message::CanvasResized(bounds.width, bounds.height)));
+                        }
+                        if state.dragging_minimap {
+                            return (event::Status::Captured, Some(jump_to_minimap(viewport)));
+                        }
+                        if let Some(start) = state.pan_start {
+                            let pan_start = state.pan_top_left_start
+                                .expect("Pan start position should be set while panning"); 
+                            return (event::Status::Captured, Some(Message::MoveViewport(
+                                pan_start.x - (state.mouse_pos.x - start.x),
+                                pan_start.y - (state.mouse_pos.y - start.y),
+                            )));
+                        }
+                        // The canvas gets mouse events from the whole window.
+                        // Leaving it ends strokes and selections, so nothing is
+                        // painted where it can't be seen.
+                        if !bounds.contains(position) {
+                            let message = state.last_cell.take().map(|_| Message::MouseReleased);
+                            return (event::Status::Ignored, message);
                         }
                         let message =
                             if state.last_cell != Some((x, y)) {
@@ -61,47 +319,115 @@ impl<'a> canvas::Program<Message> for PixelCanvas<'a> {
                         if !bounds.contains(state.mouse_pos) {
                             return (event::Status::Ignored, None);
                         }
-                        (event::Status::Captured, Some(Message::CellClicked(x, y)))
+                        if state.space_held {
+                            state.start_pan(top_left);
+                            return (event::Status::Captured, None);
+                        }
+                        if self.program_state.rulers_visible && (anchor.0 < RULER_SIZE || anchor.1 < RULER_SIZE) {
+                            state.dragging_guide = self.grab_guide(anchor);
+                            return (event::Status::Captured, None);
+                        }
+                        if self.program_state.minimap_visible && minimap.bounds.contains(Point::new(anchor.0, anchor.1)) {
+                            state.dragging_minimap = true;
+                            return (event::Status::Captured, Some(jump_to_minimap(viewport)));
+                        }
+                        (event::Status::Captured, Some(Message::CellClicked(x, y, PaintButton::Primary)))
+                    },
+                    mouse::Event::ButtonPressed(mouse::Button::Right) => {
+                        if !bounds.contains(state.mouse_pos) {
+                            return (event::Status::Ignored, None);
+                        }
+                        if self.program_state.rulers_visible && (anchor.0 < RULER_SIZE || anchor.1 < RULER_SIZE) {
+                            return (event::Status::Captured, None);
+                        }
+                        (event::Status::Captured, Some(Message::CellClicked(x, y, PaintButton::Secondary)))
                     },
                     mouse::Event::ButtonPressed(mouse::Button::Middle) => {
                         if !bounds.contains(state.mouse_pos) {
                             return (event::Status::Ignored, None);
                         }
-                        state.middle_button_start = Some(state.mouse_pos);
-                        state.middle_button_top_left_start = Some(state.top_left);
+                        state.start_pan(top_left);
                         (event::Status::Captured, None)
                     },
                     mouse::Event::WheelScrolled { delta } => {
                         match delta {
                             mouse::ScrollDelta::Lines { y, .. } => {
                                 if y > 0.0 {
-                                    (event::Status::Captured, Some(Message::ZoomIn))
+                                    (event::Status::Captured, Some(Message::ZoomIn(anchor)))
                                 } else if y < 0.0 {
-                                    (event::Status::Captured, Some(Message::ZoomOut))
+                                    (event::Status::Captured, Some(Message::ZoomOut(anchor)))
                                 } else {
                                     (event::Status::Ignored, None)
                                 }
                             },
-                            mouse::ScrollDelta::Pixels { y, .. } => {
-                                if y > 0.0 {
-                                    (event::Status::Captured, Some(Message::ZoomIn))
-                                } else if y < 0.0 {
-                                    (event::Status::Captured, Some(Message::ZoomOut))
+                            // Trackpads send many small deltas, so they zoom
+                            // proportionally instead of a whole step each, and
+                            // only while holding ctrl (which is also what pinching
+                            // sends). Otherwise two-finger scrolling pans.
+                            mouse::ScrollDelta::Pixels { x: dx, y: dy } => {
+                                if state.modifiers.control() {
+                                    if dy == 0.0 {
+                                        return (event::Status::Ignored, None);
+                                    }
+                                    let factor = Viewport::ZOOM_STEP.powf(dy / PIXELS_PER_ZOOM_STEP);
+                                    (event::Status::Captured, Some(Message::ZoomBy(factor, anchor)))
                                 } else {
-                                    (event::Status::Ignored, None)
+                                    (event::Status::Captured, Some(Message::PanBy(-dx, -dy)))
                                 }
                             },
                         }
                     },
                     mouse::Event::ButtonReleased(mouse::Button::Middle) => {
-                        state.middle_button_start = None;
+                        state.pan_start = None;
                         (event::Status::Captured, None)
                     },
-                    mouse::Event::ButtonReleased(mouse::Button::Left) =>
-                        (event::Status::Captured, Some(Message::MouseReleased)),
+                    mouse::Event::ButtonReleased(mouse::Button::Left | mouse::Button::Right) => {
+                        // Guides dropped back on the ruler they came from are removed
+                        if let Some((axis, index)) = state.dragging_guide.take() {
+                            let message = match axis {
+                                Axis::Horizontal if anchor.1 < RULER_SIZE => index.map(|index| Message::RemoveGuide(axis, index)),
+                                Axis::Vertical if anchor.0 < RULER_SIZE => index.map(|index| Message::RemoveGuide(axis, index)),
+                                Axis::Horizontal => Some(Message::MoveGuide(axis, index, y)),
+                                Axis::Vertical => Some(Message::MoveGuide(axis, index, x)),
+                            };
+                            return (event::Status::Captured, message);
+                        }
+                        state.dragging_minimap = false;
+                        // Space drags pan with the left button
+                        if state.space_held {
+                            state.pan_start = None;
+                        }
+                        (event::Status::Captured, Some(Message::MouseReleased))
+                    },
                     _ => (event::Status::Ignored, None),
                 }
             },
+            // Keyboard events are left for the app's shortcuts, the canvas
+            // only keeps track of what's held
+            canvas::Event::Keyboard(e) => {
+                match e {
+                    keyboard::Event::KeyPressed { key: keyboard::Key::Named(keyboard::key::Named::Space), .. } => {
+                        // Space presses at the keyboard cursor while it's shown
+                        state.space_held = self.program_state.keyboard_cursor.is_none();
+                    },
+                    keyboard::Event::KeyReleased { key: keyboard::Key::Named(keyboard::key::Named::Space), .. } => {
+                        state.space_held = false;
+                        state.pan_start = None;
+                    },
+                    keyboard::Event::ModifiersChanged(modifiers) => {
+                        state.modifiers = modifiers;
+                    },
+                    _ => {},
+                }
+                // Shortcuts can zoom around the center or fit the drawing
+                // before the mouse moves, like right after the window was
+                // resized. The new size is handled before the shortcut's
+                // message, which comes from the ignored event.
+                if (bounds.width, bounds.height) != viewport.size {
+                    return (event::Status::Ignored, Some(Message::CanvasResized(bounds.width, bounds.height)));
+                }
+                (event::Status::Ignored, None)
+            },
             _ => {
                 (event::Status::Ignored, None)
             }
@@ -116,99 +442,370 @@ impl<'a> canvas::Program<Message> for PixelCanvas<'a> {
         bounds: iced::Rectangle,
         _cursor: mouse::Cursor
     ) -> Vec<canvas::Geometry> {
-        let cell_size = self.program_state.cell_size;
-        let mut frame = canvas::Frame::new(renderer, bounds.size());
-        let vert_cell_count = bounds.height/self.program_state.cell_size + 1.0;
-        let horz_cell_count = bounds.width/self.program_state.cell_size + 1.0;
+        let cell_size = self.program_state.document().viewport.cell_size;
+        let top_left = Point::new(self.program_state.document().viewport.top_left.0, self.program_state.document().viewport.top_left.1);
+        let vert_cell_count = bounds.height/cell_size + 1.0;
+        let horz_cell_count = bounds.width/cell_size + 1.0;
         let stroke = canvas::Stroke::default()
             .with_width(0.7);
-        
-        // Distance from the top left corner to the next grid line to the top
-        let mod_y = if state.top_left.y < 0.0 {
-            (state.top_left.y % cell_size + cell_size) % cell_size
-        } else {
-            state.top_left.y % cell_size
-        };
 
-        // Distance from the top left corner to the next grid line to the left
-        let mod_x = if state.top_left.x < 0.0 {
-            (state.top_left.x % cell_size + cell_size) % cell_size
-        } else {
-            state.top_left.x % cell_size
+        // The cached layers only change with the grid or the viewport, not
+        // with every mouse move
+        let cache_key = CacheKey {
+            revision: self.program_state.document().layers.revision(),
+            viewport: self.program_state.document().viewport,
+            bounds: bounds.size(),
+            grid_visible: self.program_state.grid_visible,
+            major_grid_spacing: self.program_state.major_grid_spacing,
+            minimap_visible: self.program_state.minimap_visible,
         };
+        if state.cached_for.get() != Some(cache_key) {
+            state.cells_cache.clear();
+            state.grid_lines_cache.clear();
+            state.minimap_cache.clear();
+            state.cached_for.set(Some(cache_key));
+        }
+        let minimap = self.minimap(bounds.size());
+        let minimap_key = MinimapKey {
+            revision: cache_key.revision,
+            visible: self.program_state.minimap_visible,
+            bounds: minimap.bounds,
+            origin: minimap.origin,
+            scale: minimap.scale,
+        };
+        if state.minimap_cached_for.get() != Some(minimap_key) {
+            state.minimap_cells_cache.clear();
+            state.minimap_cached_for.set(Some(minimap_key));
+        }
         
-        // Draw the black squares
+        // Draw the black squares, merging each row's runs into a single rectangle
         // TODO: Add color settings
-        let start_x = (state.top_left.x / cell_size).floor() as i64;
-        let start_y = (state.top_left.y / cell_size).floor() as i64;
-        for i in 0..=vert_cell_count as i64 {
-            let y = i as f32 * cell_size - mod_y;
-            for j in 0..=horz_cell_count as i64 {
-                let x = j as f32 * cell_size - mod_x;
+        let cells = state.cells_cache.draw(renderer, bounds.size(), |frame| {
+            let start_x = (top_left.x / cell_size).floor() as GridIndex;
+            let start_y = (top_left.y / cell_size).floor() as GridIndex;
+            let end = (start_x + horz_cell_count as GridIndex, start_y + vert_cell_count as GridIndex);
+            for layer in self.program_state.document().layers.visible() {
+                let color = iced::Color { a: layer.opacity, ..iced::Color::BLACK };
+                for run in layer.grid.runs((start_x, start_y), end) {
+                    if run.value == Color::Black {
+                        frame.fill_rectangle(
+                            Point::new(
+                                run.start.0 as f32 * cell_size - top_left.x,
+                                run.start.1 as f32 * cell_size - top_left.y,
+                            ),
+                            iced::Size::new(run.length as f32 * cell_size, cell_size),
+                            color,
+                        );
+                    }
+                }
+            }
+        });
+
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+
+        // Where a click would place things, snapped like the app does
+        let mouse_cell = (
+            ((state.mouse_pos.x - bounds.x + top_left.x) / cell_size).floor() as GridIndex,
+            ((state.mouse_pos.y - bounds.y + top_left.y) / cell_size).floor() as GridIndex,
+        );
+        let placement = self.program_state.place(mouse_cell);
+        
+        // Draw the preview of the selected atom, with its copies across the
+        // symmetry axes
+        if bounds.contains(state.mouse_pos) && let Some(atom) = &self.program_state.selected_atom {
+            let cells = self.program_state.symmetry.mirror(atom.cells(placement.0, placement.1));
+            for ((x, y), color) in cells {
                 let rect = canvas::Path::rectangle(
-                    Point::new(x, y),
+                    Point::new(x as f32 * cell_size - top_left.x, y as f32 * cell_size - top_left.y),
                     iced::Size::new(cell_size, cell_size),
                 );
-                if self.program_state.grid.get(start_x + j , start_y + i) == Color::Black {
-                    frame.fill(
-                        &rect,
-                        iced::Color::BLACK
-                    );
-                }
+                let color =
+                    if color == Color::Black {
+                        iced::Color::from_rgb(0.0, 0.4, 0.9)
+                    } else {
+                        iced::Color::from_rgb(0.75, 0.85, 1.0)
+                    };
+                frame.fill(
+                    &rect,
+                    color,
+                );
             }
         }
-        
-        // Draw the preview of the selected atom
-        if bounds.contains(state.mouse_pos) && let Some(atom) = &self.program_state.selected_atom {
-            // Adding the mods first will align the grid to (0, 0) so the cell_size snapping calculation works
-            // Then substracting them will offset everything back just like the block rendering above
-            let mouse_relative_x = state.mouse_pos.x - bounds.x + mod_x;
-            let mouse_relative_y = state.mouse_pos.y - bounds.y + mod_y;
-            let start_x = (mouse_relative_x / cell_size).floor() * cell_size - mod_x;
-            let start_y = (mouse_relative_y / cell_size).floor() * cell_size - mod_y;
-            for i in 0..5 { // TODO: Unhardcode atom size
-                for j in 0..5 {
-                    let x = start_x + (j as f32 * cell_size);
-                    let y = start_y + (i as f32 * cell_size);
+
+        // Draw the cells being pasted under the mouse, or at the keyboard
+        // cursor when drawing from the keyboard
+        if let Some(floating) = &self.program_state.floating {
+            let at =
+                if let Some(cursor) = self.program_state.keyboard_cursor {
+                    Some(cursor)
+                } else if bounds.contains(state.mouse_pos) {
+                    Some(placement)
+                } else {
+                    None
+                };
+            if let Some((at_x, at_y)) = at && let Some((_, (max_x, max_y))) = floating.bounding_box() {
+                let area = canvas::Path::rectangle(
+                    Point::new(at_x as f32 * cell_size - top_left.x, at_y as f32 * cell_size - top_left.y),
+                    iced::Size::new((max_x + 1) as f32 * cell_size, (max_y + 1) as f32 * cell_size),
+                );
+                frame.fill(&area, iced::Color::from_rgba(0.75, 0.85, 1.0, 0.6));
+                for ((x, y), _) in floating.iter() {
                     let rect = canvas::Path::rectangle(
-                        Point::new(x, y),
+                        Point::new((at_x + x) as f32 * cell_size - top_left.x, (at_y + y) as f32 * cell_size - top_left.y),
                         iced::Size::new(cell_size, cell_size),
                     );
-                    let color =
-                        if atom.nth_bit(i*5 + j) == Color::Black {
-                            iced::Color::from_rgb(0.0, 0.4, 0.9)
-                        } else {
-                            iced::Color::from_rgb(0.75, 0.85, 1.0)
-                        };
-                    frame.fill(
-                        &rect,
-                        color,
-                    );
+                    frame.fill(&rect, iced::Color::from_rgb(0.0, 0.4, 0.9));
                 }
             }
         }
 
-        // Draw grid lines
-        if self.program_state.grid_visible {
-            for i in 0..=vert_cell_count as i32 {
-                let y = (i as f32 * cell_size) - state.top_left.y % cell_size;
-                let line = canvas::Path::line(
-                    iced::Point::new(0.0, y),
-                    iced::Point::new(bounds.width, y)
-                );
-                frame.stroke(&line, stroke);
+        let preview = frame.into_geometry();
+
+        // Draw grid lines, all in a single path, and the major ones over them
+        // in another
+        let grid_lines = state.grid_lines_cache.draw(renderer, bounds.size(), |frame| {
+            if !self.program_state.grid_visible {
+                return;
             }
-            for i in 0..=horz_cell_count as i32 {
-                let x = (i as f32 * cell_size).floor() - state.top_left.x % cell_size;
-                let line = canvas::Path::line(
-                    iced::Point::new(x, 0.0),
-                    iced::Point::new(x, bounds.height)
-                );
-                frame.stroke(&line, stroke);
+            let spacing = self.program_state.major_grid_spacing;
+            // Whether the line at `position` on screen, along an axis scrolled
+            // by `offset`, is a major one
+            let is_major = |position: f32, offset: f32| {
+                spacing > 1 && (((position + offset) / cell_size).round() as GridIndex).rem_euclid(spacing) == 0
+            };
+            let mut major_lines = canvas::path::Builder::new();
+            let lines = canvas::Path::new(|builder| {
+                for i in 0..=vert_cell_count as i32 {
+                    let y = (i as f32 * cell_size) - top_left.y % cell_size;
+                    builder.move_to(Point::new(0.0, y));
+                    builder.line_to(Point::new(bounds.width, y));
+                    if is_major(y, top_left.y) {
+                        major_lines.move_to(Point::new(0.0, y));
+                        major_lines.line_to(Point::new(bounds.width, y));
+                    }
+                }
+                for i in 0..=horz_cell_count as i32 {
+                    let x = (i as f32 * cell_size).floor() - top_left.x % cell_size;
+                    builder.move_to(Point::new(x, 0.0));
+                    builder.line_to(Point::new(x, bounds.height));
+                    if is_major(x, top_left.x) {
+                        major_lines.move_to(Point::new(x, 0.0));
+                        major_lines.line_to(Point::new(x, bounds.height));
+                    }
+                }
+            });
+            frame.stroke(&lines, stroke);
+            frame.stroke(&major_lines.build(), canvas::Stroke::default().with_width(1.5));
+        });
+
+        let mut frame = canvas::Frame::new(renderer, bounds.size());
+
+        // Draw the outline of the brush under the cursor
+        if bounds.contains(state.mouse_pos) &&
+           self.program_state.selected_atom.is_none() &&
+           self.program_state.floating.is_none() &&
+           self.program_state.tool.uses_brush() {
+            let cursor_x = ((state.mouse_pos.x - bounds.x + top_left.x) / cell_size).floor() as GridIndex;
+            let cursor_y = ((state.mouse_pos.y - bounds.y + top_left.y) / cell_size).floor() as GridIndex;
+            // Along with its copies across the symmetry axes
+            let footprint: HashSet<GridPoint> = self.program_state.brush
+                .footprint(cursor_x, cursor_y)
+                .into_iter()
+                .flat_map(|cell| self.program_state.symmetry.images(cell))
+                .collect();
+            let outline_stroke = canvas::Stroke::default()
+                .with_width(2.0)
+                .with_color(iced::Color::from_rgb(0.0, 0.4, 0.9));
+            let outline = canvas::Path::new(|builder| {
+                for &(x, y) in &footprint {
+                    let left = x as f32 * cell_size - top_left.x;
+                    let top = y as f32 * cell_size - top_left.y;
+                    let right = left + cell_size;
+                    let bottom = top + cell_size;
+                    // Only the edges that don't touch another cell of the brush
+                    let edges = [
+                        ((x, y - 1), Point::new(left, top), Point::new(right, top)),
+                        ((x, y + 1), Point::new(left, bottom), Point::new(right, bottom)),
+                        ((x - 1, y), Point::new(left, top), Point::new(left, bottom)),
+                        ((x + 1, y), Point::new(right, top), Point::new(right, bottom)),
+                    ];
+                    for (neighbour, from, to) in edges {
+                        if !footprint.contains(&neighbour) {
+                            builder.move_to(from);
+                            builder.line_to(to);
+                        }
+                    }
+                }
+            });
+            frame.stroke(&outline, outline_stroke);
+        }
+
+        // Draw the keyboard cursor, as big as the selected atom if there is one
+        if let Some((x, y)) = self.program_state.keyboard_cursor {
+            let side =
+                if self.program_state.selected_atom.is_some() {
+                    Atom::SIDE as f32
+                } else {
+                    1.0
+                };
+            let rect = canvas::Path::rectangle(
+                Point::new(x as f32 * cell_size - top_left.x, y as f32 * cell_size - top_left.y),
+                iced::Size::new(side * cell_size, side * cell_size),
+            );
+            frame.stroke(
+                &rect,
+                canvas::Stroke::default()
+                    .with_width(3.0)
+                    .with_color(iced::Color::from_rgb(0.9, 0.4, 0.0)),
+            );
+        }
+
+        // Draw the selection
+        if let Some(((min_x, min_y), (max_x, max_y))) = self.program_state.document().selection {
+            let rect = canvas::Path::rectangle(
+                Point::new(min_x as f32 * cell_size - top_left.x, min_y as f32 * cell_size - top_left.y),
+                iced::Size::new((max_x - min_x + 1) as f32 * cell_size, (max_y - min_y + 1) as f32 * cell_size),
+            );
+            frame.fill(&rect, iced::Color::from_rgba(0.0, 0.4, 0.9, 0.15));
+            frame.stroke(
+                &rect,
+                canvas::Stroke {
+                    line_dash: canvas::LineDash { segments: &[6.0, 4.0], offset: 0 },
+                    ..canvas::Stroke::default()
+                        .with_width(1.5)
+                        .with_color(iced::Color::from_rgb(0.0, 0.4, 0.9))
+                },
+            );
+        }
+
+        // Draw the guides, and the one being dragged where it would be dropped
+        let guide_stroke = canvas::Stroke::default()
+            .with_width(1.0)
+            .with_color(iced::Color::from_rgb(0.0, 0.7, 0.7));
+        let document = self.program_state.document();
+        let dragged = state.dragging_guide.map(|(axis, index)| match axis {
+            Axis::Horizontal => (axis, index, mouse_cell.1),
+            Axis::Vertical => (axis, index, mouse_cell.0),
+        });
+        let guides = canvas::Path::new(|builder| {
+            for axis in [Axis::Horizontal, Axis::Vertical] {
+                let lines = document.guides.lines(axis).iter().copied().enumerate().map(|(index, line)| {
+                    match dragged {
+                        Some((dragged_axis, Some(dragged_index), to)) if dragged_axis == axis && dragged_index == index => to,
+                        _ => line,
+                    }
+                });
+                let new_line = match dragged {
+                    Some((dragged_axis, None, to)) if dragged_axis == axis => Some(to),
+                    _ => None,
+                };
+                for line in lines.chain(new_line) {
+                    match axis {
+                        Axis::Horizontal => {
+                            let y = line as f32 * cell_size - top_left.y;
+                            builder.move_to(Point::new(0.0, y));
+                            builder.line_to(Point::new(bounds.width, y));
+                        },
+                        Axis::Vertical => {
+                            let x = line as f32 * cell_size - top_left.x;
+                            builder.move_to(Point::new(x, 0.0));
+                            builder.line_to(Point::new(x, bounds.height));
+                        },
+                    }
+                }
             }
+        });
+        frame.stroke(&guides, guide_stroke);
+
+        // Draw the symmetry axes, crossing at their center
+        let symmetry = self.program_state.symmetry;
+        if symmetry.mode != SymmetryMode::Off {
+            let (axis_x, axis_y) = symmetry.axes();
+            let center = Point::new(axis_x * cell_size - top_left.x, axis_y * cell_size - top_left.y);
+            let axes = canvas::Path::new(|builder| {
+                if symmetry.mode != SymmetryMode::Vertical {
+                    builder.move_to(Point::new(center.x, 0.0));
+                    builder.line_to(Point::new(center.x, bounds.height));
+                }
+                if symmetry.mode != SymmetryMode::Horizontal {
+                    builder.move_to(Point::new(0.0, center.y));
+                    builder.line_to(Point::new(bounds.width, center.y));
+                }
+            });
+            let color = iced::Color::from_rgb(0.7, 0.0, 0.7);
+            frame.stroke(&axes, canvas::Stroke::default().with_width(1.5).with_color(color));
+            frame.fill(&canvas::Path::circle(center, 4.0), color);
+        }
+
+        // Draw the line the patterns of the text being written sit on
+        if self.program_state.placement == Placement::Baseline && let Some(baseline) = self.program_state.baseline {
+            let y = (baseline + self.program_state.lattice.pitch) as f32 * cell_size - top_left.y;
+            frame.stroke(
+                &canvas::Path::line(Point::new(0.0, y), Point::new(bounds.width, y)),
+                canvas::Stroke {
+                    line_dash: canvas::LineDash { segments: &[6.0, 4.0], offset: 0 },
+                    ..canvas::Stroke::default()
+                        .with_width(2.0)
+                        .with_color(iced::Color::from_rgb(0.9, 0.4, 0.0))
+                },
+            );
+        }
+
+        if self.program_state.rulers_visible {
+            self.draw_rulers(&mut frame, bounds.size(), mouse_cell);
         }
 
+        let overlay = frame.into_geometry();
+
+        // Draw the minimap over everything else
+        let minimap_cells = state.minimap_cells_cache.draw(renderer, bounds.size(), |frame| {
+            if !self.program_state.minimap_visible {
+                return;
+            }
+            frame.fill_rectangle(
+                minimap.bounds.position(),
+                minimap.bounds.size(),
+                iced::Color { a: 0.9, ..iced::Color::WHITE },
+            );
+            if let Some((min, max)) = self.program_state.document().layers.bounding_box() {
+                for layer in self.program_state.document().layers.visible() {
+                    let color = iced::Color { a: layer.opacity, ..iced::Color::BLACK };
+                    for run in layer.grid.runs(min, max) {
+                        if run.value == Color::Black {
+                            frame.fill_rectangle(
+                                minimap.to_minimap(run.start.0 as f32, run.start.1 as f32),
+                                iced::Size::new(run.length as f32 * minimap.scale, minimap.scale),
+                                color,
+                            );
+                        }
+                    }
+                }
+            }
+            frame.stroke(
+                &canvas::Path::rectangle(minimap.bounds.position(), minimap.bounds.size()),
+                canvas::Stroke::default().with_width(1.0),
+            );
+        });
+        let minimap_view = state.minimap_cache.draw(renderer, bounds.size(), |frame| {
+            if !self.program_state.minimap_visible {
+                return;
+            }
+            let view_rect = canvas::Path::rectangle(
+                minimap.to_minimap(top_left.x / cell_size, top_left.y / cell_size),
+                iced::Size::new(
+                    bounds.width / cell_size * minimap.scale,
+                    bounds.height / cell_size * minimap.scale,
+                ),
+            );
+            frame.stroke(
+                &view_rect,
+                canvas::Stroke::default()
+                    .with_width(1.5)
+                    .with_color(iced::Color::from_rgb(0.0, 0.4, 0.9)),
+            );
+        });
+
         // Then, we produce the geometry
-        vec![frame.into_geometry()]
+        vec![cells, preview, grid_lines, overlay, minimap_cells, minimap_view]
     }
 }
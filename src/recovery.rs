@@ -0,0 +1,148 @@
+use std::path::{Path, PathBuf};
+
+use crate::{Color, Document, Grid, GridIndex, Layer, Layers};
+
+// Where unsaved documents are kept in case the editor doesn't exit cleanly,
+// next to data.csv
+pub const RECOVERY_PATH: &str = ".pixel-editor-recovery";
+
+const HEADER: &str = "pixel-editor recovery 1";
+
+// Writes the documents as text: a line per document, path and layer, each
+// layer followed by its black cells as "x y length" runs
+pub fn save_recovery<'a>(documents: impl IntoIterator<Item = &'a Document>, path: &Path) -> Result<(), String> {
+    let mut text = format!("{HEADER}\n");
+    for document in documents {
+        text += &format!("document {}\n", document.name);
+        text += &format!("path {}\n", document.path.display());
+        for layer in document.layers.iter() {
+            text += &format!(
+                "layer {} {} {} {}\n",
+                layer.visible as u8, layer.locked as u8, layer.opacity, layer.name,
+            );
+            if let Some((min, max)) = layer.grid.bounding_box() {
+                for run in layer.grid.runs(min, max) {
+                    if run.value == Color::Black {
+                        text += &format!("{} {} {}\n", run.start.0, run.start.1, run.length);
+                    }
+                }
+            }
+        }
+    }
+    // Written next to the file and moved over it, so a crash while writing
+    // can't leave only a broken copy
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, text)
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .map_err(|error| format!("Failed to write {}: {error}", path.display()))
+}
+
+// Reads documents written by `save_recovery`. They are all marked as having
+// unsaved changes.
+pub fn load_recovery(path: &Path) -> Result<Vec<Document>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| format!("Failed to read {}: {error}", path.display()))?;
+    let mut lines = text.lines();
+    if lines.next() != Some(HEADER) {
+        return Err(format!("{} isn't a recovery file", path.display()));
+    }
+
+    let mut documents = Vec::new();
+    let mut layers = Vec::new();
+    let mut document: Option<Document> = None;
+    let finish = |document: Option<Document>, layers: &mut Vec<Layer>, documents: &mut Vec<Document>| {
+        if let Some(mut document) = document {
+            document.layers = Layers::with_layers(std::mem::take(layers));
+            document.undo_history.mark_unsaved();
+            documents.push(document);
+        }
+    };
+    for (number, line) in lines.enumerate() {
+        let invalid = || format!("Invalid line {} of {}", number + 2, path.display());
+        if let Some(name) = line.strip_prefix("document ") {
+            finish(document.take(), &mut layers, &mut documents);
+            document = Some(Document::new(name.to_string()));
+        } else if let Some(file) = line.strip_prefix("path ") {
+            document.as_mut().ok_or_else(invalid)?.path = PathBuf::from(file);
+        } else if let Some(fields) = line.strip_prefix("layer ") {
+            document.as_ref().ok_or_else(invalid)?;
+            let mut fields = fields.splitn(4, ' ');
+            let mut next = || fields.next().ok_or_else(invalid);
+            let visible = next()? == "1";
+            let locked = next()? == "1";
+            let opacity = next()?.parse().map_err(|_| invalid())?;
+            let name = next()?.to_string();
+            let mut layer = Layer::new(0, Grid::default());
+            layer.name = name;
+            layer.visible = visible;
+            layer.locked = locked;
+            layer.opacity = opacity;
+            layers.push(layer);
+        } else {
+            let numbers: Vec<GridIndex> = line
+                .split_whitespace()
+                .map(|field| field.parse().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            let &[x, y, length] = numbers.as_slice() else {
+                return Err(invalid());
+            };
+            let layer = layers.last_mut().ok_or_else(invalid)?;
+            for dx in 0..length {
+                layer.grid.set(x + dx, y, Color::Black);
+            }
+        }
+    }
+    finish(document, &mut layers, &mut documents);
+    Ok(documents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GridPoint;
+
+    fn black_cells(layer: &Layer) -> Vec<GridPoint> {
+        let mut cells: Vec<_> = layer.grid.iter().map(|(cell, _)| cell).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn round_trips_documents() {
+        let mut first = Document::new("Two layers".to_string());
+        first.path = PathBuf::from("drawings/first drawing.png");
+        for x in [-3, -2, -1, 4] {
+            first.layers.active_mut().grid.set(x, -5, Color::Black);
+        }
+        let id = first.layers.add();
+        let layer = first.layers.get_mut(id).unwrap();
+        layer.name = "Ink on top".to_string();
+        layer.visible = false;
+        layer.locked = true;
+        layer.opacity = 0.25;
+        layer.grid.set(0, 0, Color::Black);
+        layer.grid.set(0, 1, Color::Black);
+        let second = Document::new("Empty".to_string());
+
+        let path = std::env::temp_dir().join(format!("pixel-editor-recovery-test-{}", std::process::id()));
+        save_recovery([&first, &second], &path).unwrap();
+        let documents = load_recovery(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].name, "Two layers");
+        assert_eq!(documents[0].path, first.path);
+        assert_eq!(documents[1].name, "Empty");
+        assert!(documents.iter().all(|document| document.undo_history.is_dirty()));
+        let layers: Vec<_> = documents[0].layers.iter().collect();
+        let originals: Vec<_> = first.layers.iter().collect();
+        assert_eq!(layers.len(), 2);
+        for (layer, original) in layers.into_iter().zip(originals) {
+            assert_eq!(layer.name, original.name);
+            assert_eq!(layer.settings(), original.settings());
+            assert_eq!(black_cells(layer), black_cells(original));
+        }
+        assert_eq!(documents[1].layers.len(), 1);
+        assert_eq!(documents[1].layers.active().grid.bounding_box(), None);
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use crate::{Action, Color, GridPoint, LayerId, Layers};
+
+// Cells painted on a layer while a mouse button is held, undone together
+pub struct Stroke {
+    layer: LayerId,
+    // Color every painted cell is given
+    color: Color,
+    // Color each cell had before the stroke first painted it
+    original: HashMap<GridPoint, Color>,
+}
+
+impl Stroke {
+    pub fn new(layer: LayerId, color: Color) -> Self {
+        Self { layer, color, original: HashMap::new() }
+    }
+
+    // Paints the cells, unless the stroke's layer was removed or can't be
+    // edited anymore
+    pub fn paint(&mut self, layers: &mut Layers, cells: impl IntoIterator<Item = GridPoint>) {
+        let Some(layer) = layers.get_mut(self.layer).filter(|layer| layer.editable()) else {
+            return;
+        };
+        for (x, y) in cells {
+            let old_color = layer.grid.get(x, y);
+            if old_color != self.color {
+                self.original.entry((x, y)).or_insert(old_color);
+                layer.grid.set(x, y, self.color);
+            }
+        }
+    }
+
+    // Label and action to record the stroke in the history with, reverting
+    // every cell it painted
+    pub fn finish(self) -> (String, Action) {
+        let label = format!("Stroke {} cells", self.original.len());
+        let cells: Vec<_> = self.original.into_iter().collect();
+        (label, Action::Paint(self.layer, cells.into()))
+    }
+}
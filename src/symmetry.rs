@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+
+use crate::{Color, Grid, GridIndex, GridPoint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymmetryMode {
+    #[default]
+    Off,
+    // Mirrors left and right across a vertical axis
+    Horizontal,
+    // Mirrors top and bottom across a horizontal axis
+    Vertical,
+    // Mirrors across both axes
+    FourWay,
+    // Repeats every quarter turn around the center
+    Radial,
+}
+
+impl SymmetryMode {
+    pub const ALL: [SymmetryMode; 5] = [
+        SymmetryMode::Off,
+        SymmetryMode::Horizontal,
+        SymmetryMode::Vertical,
+        SymmetryMode::FourWay,
+        SymmetryMode::Radial,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SymmetryMode::Off => "Off",
+            SymmetryMode::Horizontal => "Horizontal",
+            SymmetryMode::Vertical => "Vertical",
+            SymmetryMode::FourWay => "4-way",
+            SymmetryMode::Radial => "Radial",
+        }
+    }
+}
+
+// Copies every painted cell across the axes through `center`. The axes run
+// along the top left corner of the center cell, or through its middle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Symmetry {
+    pub mode: SymmetryMode,
+    pub center: GridPoint,
+    pub through_cells: bool,
+}
+
+impl Symmetry {
+    // Center of the axes in half cells, so it can lie between cells or in
+    // the middle of one
+    fn doubled_center(&self) -> GridPoint {
+        let offset = self.through_cells as GridIndex;
+        (2 * self.center.0 + offset, 2 * self.center.1 + offset)
+    }
+
+    // Center of the axes in cells, to draw them
+    pub fn axes(&self) -> (f32, f32) {
+        let (x, y) = self.doubled_center();
+        (x as f32 / 2.0, y as f32 / 2.0)
+    }
+
+    // The cell and its copies, each once
+    pub fn images(&self, (x, y): GridPoint) -> Vec<GridPoint> {
+        let (center_x, center_y) = self.doubled_center();
+        let mirrored_x = center_x - 1 - x;
+        let mirrored_y = center_y - 1 - y;
+        let mut images = match self.mode {
+            SymmetryMode::Off => vec![(x, y)],
+            SymmetryMode::Horizontal => vec![(x, y), (mirrored_x, y)],
+            SymmetryMode::Vertical => vec![(x, y), (x, mirrored_y)],
+            SymmetryMode::FourWay => vec![(x, y), (mirrored_x, y), (x, mirrored_y), (mirrored_x, mirrored_y)],
+            SymmetryMode::Radial => {
+                // Middle of the cell relative to the center, in half cells.
+                // Both parts of the center are even or both odd, so every
+                // turn lands on the middle of a cell.
+                let (dx, dy) = (2 * x + 1 - center_x, 2 * y + 1 - center_y);
+                let cell = |dx: GridIndex, dy: GridIndex| {
+                    ((center_x + dx - 1).div_euclid(2), (center_y + dy - 1).div_euclid(2))
+                };
+                vec![cell(dx, dy), cell(-dy, dx), cell(-dx, -dy), cell(dy, -dx)]
+            },
+        };
+        images.sort_unstable();
+        images.dedup();
+        images
+    }
+
+    // The cells and their copies, with the given cells first and copies that
+    // land on one of them left out, so a pattern crossing an axis keeps its
+    // own cells
+    pub fn mirror(&self, cells: impl IntoIterator<Item = (GridPoint, Color)>) -> Vec<(GridPoint, Color)> {
+        let cells: Vec<_> = cells.into_iter().collect();
+        let originals: HashSet<GridPoint> = cells.iter().map(|&(cell, _)| cell).collect();
+        let copies: Vec<_> = cells
+            .iter()
+            .flat_map(|&(cell, color)| self.images(cell).into_iter().map(move |image| (image, color)))
+            .filter(|(image, _)| !originals.contains(image))
+            .collect();
+        cells.into_iter().chain(copies).collect()
+    }
+
+    // Sets the cells and their copies, returning the previous colors of
+    // everything set
+    pub fn paint(&self, grid: &mut Grid<Color>, cells: impl IntoIterator<Item = (GridPoint, Color)>) -> Vec<(GridPoint, Color)> {
+        self.mirror(cells)
+            .into_iter()
+            .map(|((x, y), color)| {
+                let previous = grid.get(x, y);
+                grid.set(x, y, color);
+                ((x, y), previous)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symmetry(mode: SymmetryMode, through_cells: bool) -> Symmetry {
+        Symmetry { mode, center: (2, 2), through_cells }
+    }
+
+    #[test]
+    fn images_between_cells() {
+        let cell = (0, 1);
+        assert_eq!(symmetry(SymmetryMode::Off, false).images(cell), vec![(0, 1)]);
+        assert_eq!(symmetry(SymmetryMode::Horizontal, false).images(cell), vec![(0, 1), (3, 1)]);
+        assert_eq!(symmetry(SymmetryMode::Vertical, false).images(cell), vec![(0, 1), (0, 2)]);
+        assert_eq!(symmetry(SymmetryMode::FourWay, false).images(cell), vec![(0, 1), (0, 2), (3, 1), (3, 2)]);
+        assert_eq!(symmetry(SymmetryMode::Radial, false).images(cell), vec![(0, 1), (1, 3), (2, 0), (3, 2)]);
+    }
+
+    #[test]
+    fn images_through_cells() {
+        let cell = (2, 0);
+        assert_eq!(symmetry(SymmetryMode::Off, true).images(cell), vec![(2, 0)]);
+        // On the axis, so it's its own copy
+        assert_eq!(symmetry(SymmetryMode::Horizontal, true).images(cell), vec![(2, 0)]);
+        assert_eq!(symmetry(SymmetryMode::Vertical, true).images(cell), vec![(2, 0), (2, 4)]);
+        assert_eq!(symmetry(SymmetryMode::FourWay, true).images(cell), vec![(2, 0), (2, 4)]);
+        assert_eq!(symmetry(SymmetryMode::Radial, true).images(cell), vec![(0, 2), (2, 0), (2, 4), (4, 2)]);
+        assert_eq!(symmetry(SymmetryMode::Radial, true).images((2, 2)), vec![(2, 2)]);
+        assert_eq!(symmetry(SymmetryMode::FourWay, true).images((0, 1)), vec![(0, 1), (0, 3), (4, 1), (4, 3)]);
+    }
+
+    #[test]
+    fn pattern_crossing_an_axis_keeps_its_cells() {
+        // Axis between columns 1 and 2
+        let symmetry = Symmetry { mode: SymmetryMode::Horizontal, center: (2, 0), through_cells: false };
+        let pattern = [((0, 0), Color::Black), ((1, 0), Color::Black), ((2, 0), Color::White)];
+        assert_eq!(symmetry.mirror(pattern), vec![
+            ((0, 0), Color::Black),
+            ((1, 0), Color::Black),
+            ((2, 0), Color::White),
+            ((3, 0), Color::Black),
+        ]);
+
+        let mut grid = Grid::default();
+        grid.set(2, 0, Color::Black);
+        let previous = symmetry.paint(&mut grid, pattern);
+        assert_eq!(previous.len(), 4);
+        assert_eq!(grid.get(2, 0), Color::White);
+        assert_eq!(grid.get(1, 0), Color::Black);
+        assert_eq!(grid.get(3, 0), Color::Black);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{Color, Grid, GridIndex, GridPoint};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextStyle {
+    // One character per cell, `#` for black and `.` for white
+    #[default]
+    Ascii,
+    // Two rows per line with the upper and lower half block characters
+    HalfBlocks,
+    // 2x4 cells per character with the Braille patterns
+    Braille,
+}
+
+// Width and height in cells of each character
+fn cell_size(style: TextStyle) -> (GridIndex, GridIndex) {
+    match style {
+        TextStyle::Ascii => (1, 1),
+        TextStyle::HalfBlocks => (1, 2),
+        TextStyle::Braille => (2, 4),
+    }
+}
+
+const BRAILLE_BASE: u32 = 0x2800;
+
+// Bit of each dot of a Braille character, indexed by [row][column]
+const BRAILLE_DOTS: [[u32; 2]; 4] = [
+    [0x01, 0x08],
+    [0x02, 0x10],
+    [0x04, 0x20],
+    [0x40, 0x80],
+];
+
+// Character for the cells whose top left corner is `(x, y)`, leaving out the
+// cells past `max`
+fn encode(grid: &Grid<Color>, style: TextStyle, (x, y): GridPoint, (max_x, max_y): GridPoint) -> char {
+    let black = |dx: GridIndex, dy: GridIndex| {
+        x + dx <= max_x && y + dy <= max_y && grid.get(x + dx, y + dy) == Color::Black
+    };
+    match style {
+        TextStyle::Ascii => if black(0, 0) { '#' } else { '.' },
+        TextStyle::HalfBlocks => match (black(0, 0), black(0, 1)) {
+            (true, true) => '█',
+            (true, false) => '▀',
+            (false, true) => '▄',
+            (false, false) => ' ',
+        },
+        TextStyle::Braille => {
+            let mut bits = 0;
+            for (dy, row) in BRAILLE_DOTS.iter().enumerate() {
+                for (dx, bit) in row.iter().enumerate() {
+                    if black(dx as GridIndex, dy as GridIndex) {
+                        bits |= bit;
+                    }
+                }
+            }
+            char::from_u32(BRAILLE_BASE + bits).expect("Braille patterns are valid characters")
+        },
+    }
+}
+
+// Black cells of a character, relative to its top left corner
+fn decode(style: TextStyle, c: char) -> Vec<GridPoint> {
+    match style {
+        TextStyle::Ascii => match c {
+            '#' | 'X' | 'x' | '*' | '1' => vec![(0, 0)],
+            _ => vec![],
+        },
+        TextStyle::HalfBlocks => match c {
+            '█' => vec![(0, 0), (0, 1)],
+            '▀' => vec![(0, 0)],
+            '▄' => vec![(0, 1)],
+            _ => vec![],
+        },
+        TextStyle::Braille => {
+            let bits = (c as u32).wrapping_sub(BRAILLE_BASE);
+            if bits > 0xff {
+                return vec![];
+            }
+            let mut cells = Vec::new();
+            for (dy, row) in BRAILLE_DOTS.iter().enumerate() {
+                for (dx, bit) in row.iter().enumerate() {
+                    if bits & bit != 0 {
+                        cells.push((dx as GridIndex, dy as GridIndex));
+                    }
+                }
+            }
+            cells
+        },
+    }
+}
+
+// Text drawing of the cells inside the given corners (both inclusive), one
+// line per row of characters
+pub fn to_text(grid: &Grid<Color>, style: TextStyle, (min_x, min_y): GridPoint, max @ (max_x, max_y): GridPoint) -> String {
+    let (width, height) = cell_size(style);
+    let mut text = String::new();
+    let mut y = min_y;
+    while y <= max_y {
+        let mut x = min_x;
+        while x <= max_x {
+            text.push(encode(grid, style, (x, y), max));
+            x += width;
+        }
+        text.push('\n');
+        y += height;
+    }
+    text
+}
+
+// Guesses the style of a text drawing from the characters it uses
+pub fn detect_style(text: &str) -> TextStyle {
+    if text.chars().any(|c| (BRAILLE_BASE..=BRAILLE_BASE + 0xff).contains(&(c as u32))) {
+        TextStyle::Braille
+    } else if text.chars().any(|c| matches!(c, '▀' | '▄' | '█')) {
+        TextStyle::HalfBlocks
+    } else {
+        TextStyle::Ascii
+    }
+}
+
+// Whether the text only has characters a text drawing is made of, with some
+// black cells and more than one row or column of them, so other text like a
+// number or an ellipsis isn't mistaken for one
+pub fn is_text_drawing(text: &str) -> bool {
+    let style = detect_style(text);
+    let only_drawing = text.chars().all(|c| {
+        c.is_whitespace() || match style {
+            TextStyle::Ascii => matches!(c, '#' | 'X' | 'x' | '*' | '1' | '.' | '0'),
+            TextStyle::HalfBlocks => matches!(c, '█' | '▀' | '▄'),
+            TextStyle::Braille => (BRAILLE_BASE..=BRAILLE_BASE + 0xff).contains(&(c as u32)),
+        }
+    });
+    let any_black = text.chars().any(|c| !decode(style, c).is_empty());
+    let (width, height) = cell_size(style);
+    let rows = text.lines().filter(|line| !line.trim().is_empty()).count() as GridIndex * height;
+    let columns = text.lines().map(|line| line.trim_end().chars().count()).max().unwrap_or(0) as GridIndex * width;
+    only_drawing && any_black && (rows > 1 || columns > 1)
+}
+
+// Reads a text drawing in any style, with its first character at the origin
+pub fn from_text(text: &str) -> Grid<Color> {
+    let style = detect_style(text);
+    let (width, height) = cell_size(style);
+    let mut grid = Grid::default();
+    for (row, line) in text.lines().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            for (dx, dy) in decode(style, c) {
+                grid.set(column as GridIndex * width + dx, row as GridIndex * height + dy, Color::Black);
+            }
+        }
+    }
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::tests::grid_with;
+
+    const STYLES: [TextStyle; 3] = [TextStyle::Ascii, TextStyle::HalfBlocks, TextStyle::Braille];
+
+    fn black_cells(grid: &Grid<Color>) -> Vec<GridPoint> {
+        let mut cells: Vec<_> = grid.iter().filter(|&(_, color)| color == Color::Black).map(|(cell, _)| cell).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn round_trips_every_style() {
+        // Sizes that aren't multiples of the Braille character size
+        let cells = [(0, 0), (1, 0), (2, 1), (0, 2), (4, 4), (3, 6)];
+        let grid = grid_with(&cells, Color::Black);
+        for style in STYLES {
+            let text = to_text(&grid, style, (0, 0), (4, 6));
+            assert!(is_text_drawing(&text), "{style:?}");
+            assert_eq!(detect_style(&text), style);
+            assert_eq!(black_cells(&from_text(&text)), black_cells(&grid), "{style:?}");
+        }
+    }
+
+    #[test]
+    fn other_text_isnt_a_drawing() {
+        for text in ["1", "x", "0", "...", "", "  \n", "hello", "#.# b"] {
+            assert!(!is_text_drawing(text), "{text:?}");
+        }
+        for text in ["#.", "#\n.", ".#.\n#.#\n", "▀", "⠁"] {
+            assert!(is_text_drawing(text), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn leaves_out_cells_past_the_region() {
+        let grid = grid_with(&[(0, 0), (1, 0), (0, 1), (1, 4), (5, 5)], Color::Black);
+        for style in STYLES {
+            let text = to_text(&grid, style, (0, 0), (0, 0));
+            assert_eq!(black_cells(&from_text(&text)), vec![(0, 0)], "{style:?}");
+        }
+    }
+}